@@ -0,0 +1,681 @@
+//! Commands for using the journal from scripts and other programs, without starting the
+//! interface.
+
+use entry::{Entry, EntryState, INDENT};
+use exchange::{self, ImportError, Pages};
+use page::{self, calendar_line, valid_collection_name, Page};
+use query::Query;
+use storage::{self, Storage, StorageError};
+use tz;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json;
+use uuid::Uuid;
+
+const USAGE: &str = "Usage: bullet-terminal [<command> [<args>]]
+
+With no command the journal is opened. Commands:
+  add <text> [<page>] [--type task|note|event]
+                           Add an entry to the end of a page and print its id
+  list [<page>]            Print the entries of a page with their ids
+  done <id>                Mark a task as done
+  schedule <id> <date>     Schedule a task for another day, copying it there
+  show [--month [YYYY-MM]] Print the monthly log: each day's events, then the month's tasks
+  query <query>            Print every entry matching a query like state:open tag:work after:2024-01-01
+  export <format> [<page> | --from <date> --to <date>]
+                           Print pages in another format. A month prints the monthly log, then
+                           its days.
+  import <format> <file> [<page>]
+                           Add the entries in a file (- for stdin) to the end of their pages.
+                           Entries the file doesn't put on a page go on <page>.
+  upgrade [--dry-run]      Move a journal written by an older version into the current layout,
+                           printing each change. --dry-run only prints them.
+  help                     Print this message
+
+add, list, show and query print entries as text, or as JSON with --format json (an array) or
+--format ndjson (an object per line). Each object has the entry's page, id, origin, state, the
+date it was scheduled or migrated for, signifiers, content, tags, contexts and depth.
+
+Pages are picked with --date <date> (the default being today), --month [YYYY-MM], --future or
+--collection <name>. Dates are YYYY-MM-DD, today, yesterday or tomorrow. Ids can be shortened to
+any unique start, as printed by list.
+
+export and import understand markdown, ics, todotxt and org. Importing ics adds its events to the
+days they happen on, and importing todotxt adds its tasks to the days they were created.";
+
+#[derive(Debug)]
+enum CliError {
+    /// The command line doesn't make sense
+    Usage(String),
+    Storage(StorageError),
+    /// Printing failed
+    Output(io::Error),
+    /// The file being imported can't be read or doesn't make sense
+    Input(String),
+}
+
+impl From<StorageError> for CliError {
+    fn from(error: StorageError) -> CliError {
+        CliError::Storage(error)
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(error: io::Error) -> CliError {
+        CliError::Output(error)
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(error: serde_json::Error) -> CliError {
+        CliError::Output(error.into())
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(msg) => write!(f, "{}", msg),
+            CliError::Storage(e) => write!(f, "Unable to update the journal: {}", e),
+            CliError::Output(e) => write!(f, "Unable to print: {}", e),
+            CliError::Input(msg) => write!(f, "Unable to import: {}", msg),
+        }
+    }
+}
+
+fn usage<T, S: Into<String>>(msg: S) -> Result<T, CliError> {
+    Err(CliError::Usage(msg.into()))
+}
+
+/// Options followed by a value
+const VALUE_OPTIONS: [&str; 6] = ["date", "collection", "type", "format", "from", "to"];
+
+/// Whether the word after an option is its value. `--month` only takes one that looks like a month,
+/// and flags like `--future` never do.
+fn takes_value(option: &str, next: &str) -> bool {
+    match option {
+        "month" => NaiveDate::parse_from_str(&format!("{}-01", next), "%Y-%m-%d").is_ok(),
+        _ => VALUE_OPTIONS.contains(&option)
+    }
+}
+
+/// A command line split into its words and its `--option value` pairs. Options without a value
+/// are kept with an empty one.
+struct Args {
+    words: Vec<String>,
+    options: BTreeMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Args {
+        let mut words = Vec::new();
+        let mut options = BTreeMap::new();
+        let mut args = args.iter().peekable();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(option) => {
+                    let (name, value) = match option.find('=') {
+                        Some(idx) => (option[..idx].to_string(), option[idx + 1..].to_string()),
+                        None if args.peek().is_some_and(|next| !next.starts_with("--") && takes_value(option, next)) =>
+                            (option.to_string(), args.next().cloned().unwrap_or_default()),
+                        None => (option.to_string(), String::new())
+                    };
+                    options.insert(name, value);
+                }
+                None => words.push(arg.clone())
+            }
+        }
+        Args {words, options}
+    }
+
+    /// Complain about options the command doesn't know and words it doesn't expect
+    fn expect(&self, words: usize, options: &[&str]) -> Result<(), CliError> {
+        if let Some(option) = self.options.keys().find(|option| !options.contains(&option.as_str())) {
+            return usage(format!("Unknown option --{}", option));
+        }
+        if self.words.len() > words {
+            return usage(format!("Unexpected '{}'", self.words[words]));
+        }
+        Ok(())
+    }
+
+    fn word(&self, idx: usize, name: &str) -> Result<&str, CliError> {
+        match self.words.get(idx) {
+            Some(word) => Ok(word),
+            None => usage(format!("Missing the {}", name))
+        }
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    /// The page picked by `--date`, `--month`, `--future` or `--collection`, today's by default
+    fn page(&self) -> Result<Page, CliError> {
+        let picked: Vec<&str> = PAGE_OPTIONS.iter().cloned().filter(|option| self.options.contains_key(*option)).collect();
+        if picked.len() > 1 {
+            return usage(format!("Only one of --{} can be used", picked.join(" and --")));
+        }
+        match (picked.first().cloned(), picked.first().and_then(|option| self.option(option))) {
+            (Some("date"), Some(date)) => Ok(Page::Day(day(date)?)),
+            (Some("month"), Some(month)) => Ok(Page::month_of(month_of(month)?)),
+            (Some("future"), _) => Ok(Page::Future),
+            (Some("collection"), Some(name)) if valid_collection_name(name) => Ok(Page::Collection(name.trim().to_string())),
+            (Some("collection"), Some(name)) => usage(format!("'{}' can't be used as a collection name", name)),
+            _ => Ok(Page::Day(tz::home().today()))
+        }
+    }
+}
+
+/// The options picking a page
+const PAGE_OPTIONS: [&str; 4] = ["date", "month", "future", "collection"];
+
+/// How entries are printed
+#[derive(Clone, Copy)]
+enum Format {
+    Text,
+    /// A single array, for tools reading the whole thing at once
+    Json,
+    /// An object per line, for tools reading entries as they come
+    Ndjson,
+}
+
+impl Format {
+    /// The format picked with `--format`, text by default
+    fn of(args: &Args) -> Result<Format, CliError> {
+        match args.option("format") {
+            None | Some("text") => Ok(Format::Text),
+            Some("json") => Ok(Format::Json),
+            Some("ndjson") => Ok(Format::Ndjson),
+            Some(other) => usage(format!("Unknown format '{}'. Try text, json or ndjson", other))
+        }
+    }
+}
+
+/// An entry with the page it's on, as the JSON formats print it
+#[derive(Serialize)]
+struct Listed<'a> {
+    page: &'a Page,
+    #[serde(flatten)]
+    entry: &'a Entry,
+}
+
+/// Print entries with the pages they're on, using `text` for each line of the text format
+fn print_entries<F>(out: &mut dyn Write, format: Format, found: &[(Page, Entry)], text: F) -> Result<(), CliError>
+    where F: Fn(&Page, &Entry) -> String
+{
+    match format {
+        Format::Text => {
+            for (page, entry) in found {
+                writeln!(out, "{}", text(page, entry))?;
+            }
+        }
+        Format::Json => {
+            let listed: Vec<Listed> = found.iter().map(|(page, entry)| Listed {page, entry}).collect();
+            serde_json::to_writer_pretty(&mut *out, &listed)?;
+            writeln!(out)?;
+        }
+        Format::Ndjson => {
+            for (page, entry) in found {
+                serde_json::to_writer(&mut *out, &Listed {page, entry})?;
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn day(text: &str) -> Result<NaiveDate, CliError> {
+    tz::parse_day(text).map_err(CliError::Usage)
+}
+
+/// A month written as `YYYY-MM`, or the current one when empty
+fn month_of(text: &str) -> Result<NaiveDate, CliError> {
+    if text.is_empty() {
+        return Ok(tz::home().today());
+    }
+    NaiveDate::parse_from_str(&format!("{}-01", text), "%Y-%m-%d")
+        .or_else(|_| usage(format!("Invalid month '{}'. Months look like 2024-05", text)))
+}
+
+/// Run the command in `args` (without the program name), returning the exit status
+pub fn run(args: &[String]) -> i32 {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), Args::parse(args)),
+        None => ("help", Args::parse(&[]))
+    };
+    if command == "help" || command == "--help" || command == "-h" {
+        println!("{}", USAGE);
+        return 0;
+    }
+    let stdout = io::stdout();
+    let out = &mut stdout.lock();
+    let result = execute(&*storage::current(), command, &args, out);
+    match result.and_then(|()| Ok(out.flush()?)) {
+        Ok(()) => 0,
+        // Whatever was reading the output has seen enough, like `head`
+        Err(CliError::Output(ref e)) if e.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            match e {
+                CliError::Usage(_) => 2,
+                CliError::Storage(_) | CliError::Output(_) | CliError::Input(_) => 1,
+            }
+        }
+    }
+}
+
+/// Run a command other than `help` against the storage
+fn execute(storage: &dyn Storage, command: &str, args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    match storage.pending_upgrade()? {
+        _ if command == "upgrade" => upgrade(storage, args, out),
+        ref changes if !changes.is_empty() =>
+            usage("The journal was written by an older version. Run bullet-terminal upgrade first"),
+        _ => match command {
+            "add" => add(storage, args, out),
+            "list" => list(storage, args, out),
+            "done" => done(storage, args),
+            "schedule" => schedule(storage, args),
+            "show" => show(storage, args, out),
+            "query" => query(storage, args, out),
+            "export" => export(storage, args, out),
+            "import" => import(storage, args, out),
+            _ => usage(format!("Unknown command '{}'\n\n{}", command, USAGE))
+        }
+    }
+}
+
+/// The id as `list` prints it. Long enough to be unique in any sensible journal.
+fn short_id(id: &Uuid) -> String {
+    id.simple().to_string()[..8].to_string()
+}
+
+/// An entry as `list` prints it: its short id, then the entry indented under its parent
+fn line(entry: &Entry) -> String {
+    format!("{} {}{}{}", short_id(&entry.id), entry.gutter(), INDENT.repeat(entry.depth), entry.to_display())
+}
+
+/// The entry with the id, or the only one whose id starts with it
+fn find(storage: &dyn Storage, id: &str) -> Result<(Page, Entry), CliError> {
+    if let Ok(id) = Uuid::parse_str(id) {
+        return match storage.find(id)? {
+            Some(found) => Ok(found),
+            None => usage(format!("There's no entry with the id {}", id))
+        };
+    }
+    let prefix = id.replace('-', "").to_lowercase();
+    let mut found = storage.entries()?.into_iter()
+        .filter(|(_, entry)| !prefix.is_empty() && entry.id.simple().to_string().starts_with(&prefix));
+    match (found.next(), found.next()) {
+        (Some(found), None) => Ok(found),
+        (None, _) => usage(format!("There's no entry with an id starting with {}", id)),
+        (Some(_), Some(_)) => usage(format!("More than one entry's id starts with {}", id))
+    }
+}
+
+/// Change one entry on its page
+fn update(storage: &dyn Storage, page: &Page, updated: &Entry) -> Result<(), CliError> {
+    let mut entries = storage.load(page)?;
+    for entry in entries.iter_mut().filter(|entry| entry.id == updated.id) {
+        *entry = updated.clone();
+    }
+    Ok(storage.save(page, &entries)?)
+}
+
+fn append(storage: &dyn Storage, page: &Page, entry: Entry) -> Result<(), CliError> {
+    let mut entries = storage.load(page)?;
+    entries.push(entry);
+    Ok(storage.save(page, &entries)?)
+}
+
+fn add(storage: &dyn Storage, args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    args.expect(1, &["date", "month", "future", "collection", "type", "format"])?;
+    let format = Format::of(args)?;
+    let text = args.word(0, "text of the entry")?;
+    if text.trim().is_empty() {
+        return usage("Entries can't be empty");
+    }
+    let state = match args.option("type") {
+        None | Some("task") => EntryState::Incomplete,
+        Some("note") => EntryState::Note,
+        Some("event") => EntryState::Event,
+        Some(other) => return usage(format!("Unknown entry type '{}'. Try task, note or event", other))
+    };
+    let page = args.page()?;
+    let entry = Entry::new(text.trim(), state);
+    append(storage, &page, entry.clone())?;
+    print_entries(out, format, &[(page, entry)], |_, entry| short_id(&entry.id))
+}
+
+fn list(storage: &dyn Storage, args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    args.expect(0, &["date", "month", "future", "collection", "format"])?;
+    let format = Format::of(args)?;
+    let page = args.page()?;
+    let found: Vec<(Page, Entry)> = storage.load(&page)?.into_iter().map(|entry| (page.clone(), entry)).collect();
+    print_entries(out, format, &found, |_, entry| line(entry))
+}
+
+fn done(storage: &dyn Storage, args: &Args) -> Result<(), CliError> {
+    args.expect(1, &[])?;
+    let (page, mut entry) = find(storage, args.word(0, "id of the task")?)?;
+    if !entry.state.is_open() {
+        return usage(format!("'{}' isn't an open task", entry.content));
+    }
+    entry.state = EntryState::Completed;
+    update(storage, &page, &entry)
+}
+
+/// Mark the task as scheduled (<) and add a fresh copy of it to the day, like `<` does
+fn schedule(storage: &dyn Storage, args: &Args) -> Result<(), CliError> {
+    args.expect(2, &[])?;
+    let (page, mut entry) = find(storage, args.word(0, "id of the task")?)?;
+    let target = day(args.word(1, "date to schedule it for")?)?;
+    if !entry.state.is_open() {
+        return usage(format!("'{}' isn't an open task", entry.content));
+    }
+    let copy = entry.copy(EntryState::Incomplete);
+    entry.state = EntryState::Scheduled(tz::anchor(target));
+    update(storage, &page, &entry)?;
+    append(storage, &Page::Day(target), copy)
+}
+
+/// Print the monthly log: every day of the month with its events, then the month's tasks. The
+/// JSON formats list the same entries.
+fn show(storage: &dyn Storage, args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    args.expect(0, &["month", "format"])?;
+    let format = Format::of(args)?;
+    let date = month_of(args.option("month").unwrap_or(""))?;
+    let page = Page::month_of(date);
+    let month_entries = storage.entries_between(page::first_of_month(date), page::add_months(date, 1))?;
+    if let Format::Json | Format::Ndjson = format {
+        let found: Vec<(Page, Entry)> = month_entries.into_iter()
+            .filter(|(_, entry)| matches!(entry.state, EntryState::Event))
+            .map(|(day, entry)| (Page::Day(day), entry))
+            .chain(storage.load(&page)?.into_iter().map(|entry| (page.clone(), entry)))
+            .collect();
+        return print_entries(out, format, &found, |_, _| String::new());
+    }
+    writeln!(out, "{}", page)?;
+    for day in page::days_of_month(date) {
+        writeln!(out, "{}", calendar_line(day, &month_entries))?;
+    }
+    writeln!(out, "\nTasks")?;
+    for entry in storage.load(&page)? {
+        writeln!(out, "{}", line(&entry))?;
+    }
+    Ok(())
+}
+
+/// Print the entries matching a query, one per line with the page they're on
+fn query(storage: &dyn Storage, args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    args.expect(usize::MAX, &["format"])?;
+    let format = Format::of(args)?;
+    let query = Query::parse(&args.words.join(" ")).map_err(|e| CliError::Usage(e.to_string()))?;
+    let (found, unreadable) = query.run(storage)?;
+    for (page, e) in unreadable {
+        eprintln!("Skipped {}, which can't be read: {}", page, e);
+    }
+    print_entries(out, format, &found, |page, entry| format!("{:<12} {}", page.to_string(), line(entry)))
+}
+
+/// Bring a journal written by an older version up to date, printing each change
+fn upgrade(storage: &dyn Storage, args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    args.expect(0, &["dry-run"])?;
+    for change in storage.pending_upgrade()? {
+        writeln!(out, "{}", change)?;
+    }
+    if args.option("dry-run").is_none() {
+        storage.upgrade()?;
+    }
+    Ok(())
+}
+
+/// A format pages can be exported to and imported from
+#[derive(Clone, Copy)]
+enum Exchange {
+    Markdown,
+    Ics,
+    TodoTxt,
+    Org,
+}
+
+impl Exchange {
+    fn of(name: &str) -> Result<Exchange, CliError> {
+        match name {
+            "markdown" | "md" => Ok(Exchange::Markdown),
+            "ics" | "ical" => Ok(Exchange::Ics),
+            "todotxt" | "todo.txt" => Ok(Exchange::TodoTxt),
+            "org" => Ok(Exchange::Org),
+            other => usage(format!("Unknown format '{}'. Try markdown, ics, todotxt or org", other))
+        }
+    }
+
+    fn export(self, pages: &[(Page, Vec<Entry>)]) -> String {
+        match self {
+            Exchange::Markdown => exchange::markdown::export(pages),
+            Exchange::Ics => exchange::ics::export(pages),
+            Exchange::TodoTxt => exchange::todotxt::export(pages),
+            Exchange::Org => exchange::org::export(pages),
+        }
+    }
+
+    fn import(self, text: &str, default: &Page) -> Result<Pages, ImportError> {
+        match self {
+            Exchange::Markdown => exchange::markdown::import(text, default),
+            // Events go on the days they happen
+            Exchange::Ics => exchange::ics::import(text),
+            Exchange::TodoTxt => exchange::todotxt::import(text, default),
+            Exchange::Org => exchange::org::import(text, default),
+        }
+    }
+}
+
+/// The pages to export: the days from `--from` to `--to`, a month's log with its days, or the
+/// single page picked. Pages without entries are left out.
+fn exported_pages(storage: &dyn Storage, args: &Args) -> Result<Pages, CliError> {
+    let range = match (args.option("from"), args.option("to")) {
+        (None, None) => None,
+        (Some(from), Some(to)) if PAGE_OPTIONS.iter().all(|option| args.option(option).is_none()) =>
+            Some((day(from)?, day(to)?)),
+        (Some(_), Some(_)) => return usage("--from and --to can't be used with another page"),
+        _ => return usage("--from and --to have to be used together")
+    };
+    let picked = match (range, args.page()?) {
+        (Some((from, to)), _) => storage.list()?.into_iter()
+            .filter(|page| matches!(page, Page::Day(day) if *day >= from && *day <= to))
+            .collect(),
+        (None, Page::Month(date)) => {
+            let days = page::days_of_month(date);
+            let mut pages = vec![Page::month_of(date)];
+            pages.extend(storage.list()?.into_iter().filter(|page| matches!(page, Page::Day(day) if days.contains(day))));
+            pages
+        }
+        (None, page) => vec![page]
+    };
+    let mut pages = Pages::new();
+    for page in picked {
+        let entries = storage.load(&page)?;
+        if !entries.is_empty() {
+            pages.push((page, entries));
+        }
+    }
+    Ok(pages)
+}
+
+/// Print pages in another format
+fn export(storage: &dyn Storage, args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    args.expect(1, &["date", "month", "future", "collection", "from", "to"])?;
+    let exchange = Exchange::of(args.word(0, "format to export to")?)?;
+    let pages = exported_pages(storage, args)?;
+    write!(out, "{}", exchange.export(&pages))?;
+    Ok(())
+}
+
+/// Add the entries of a file to the end of their pages
+fn import(storage: &dyn Storage, args: &Args, out: &mut dyn Write) -> Result<(), CliError> {
+    args.expect(2, &["date", "month", "future", "collection"])?;
+    let exchange = Exchange::of(args.word(0, "format to import from")?)?;
+    let path = args.word(1, "file to import (- for stdin)")?;
+    let default = args.page()?;
+    let mut text = String::new();
+    let read = match path {
+        "-" => io::stdin().read_to_string(&mut text).map(|_| ()),
+        _ => fs::read_to_string(path).map(|contents| text = contents)
+    };
+    read.map_err(|e| CliError::Input(format!("{}: {}", path, e)))?;
+    let pages = exchange.import(&text, &default).map_err(|e| CliError::Input(format!("{}: {}", path, e)))?;
+    let mut added = 0;
+    for (page, imported) in &pages {
+        let mut entries = storage.load(page)?;
+        entries.extend(imported.iter().cloned());
+        storage.save(page, &entries)?;
+        added += imported.len();
+    }
+    writeln!(out, "Imported {} {}", added, if added == 1 { "entry" } else { "entries" })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entry::Signifier;
+    use storage::MemoryStorage;
+
+    /// Run a command line against the storage, returning what it printed
+    fn cli(storage: &dyn Storage, line: &[&str]) -> Result<String, CliError> {
+        let (command, args) = line.split_first().expect("A command");
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut out = Vec::new();
+        execute(storage, command, &Args::parse(&args), &mut out)?;
+        Ok(String::from_utf8(out).expect("Output is UTF-8"))
+    }
+
+    fn contents(storage: &dyn Storage, page: &Page) -> Vec<String> {
+        storage.load(page).unwrap().into_iter().map(|entry| entry.content).collect()
+    }
+
+    /// An entry with a made up id, so ids can share a start
+    fn with_id(content: &str, state: EntryState, id: &str) -> Entry {
+        Entry {id: Uuid::parse_str(id).unwrap(), ..Entry::new(content, state)}
+    }
+
+    #[test]
+    fn flags_leave_the_next_word_alone() {
+        let storage = MemoryStorage::default();
+        cli(&storage, &["add", "--future", "Renew the passport"]).unwrap();
+        cli(&storage, &["add", "--month", "Pay rent"]).unwrap();
+        cli(&storage, &["add", "--month", "2024-05", "Taxes"]).unwrap();
+        cli(&storage, &["add", "--month=2024-06", "Holiday"]).unwrap();
+        assert_eq!(contents(&storage, &Page::Future), vec!["Renew the passport"]);
+        assert_eq!(contents(&storage, &Page::month_of(tz::home().today())), vec!["Pay rent"]);
+        assert_eq!(contents(&storage, &Page::Month(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap())), vec!["Taxes"]);
+        assert_eq!(contents(&storage, &Page::Month(NaiveDate::from_ymd_opt(2024, 6, 1).unwrap())), vec!["Holiday"]);
+        assert!(matches!(cli(&storage, &["list", "--future", "extra"]), Err(CliError::Usage(_))));
+    }
+
+    #[test]
+    fn ids_can_be_shortened_while_theyre_unique() {
+        let storage = MemoryStorage::default();
+        let page = Page::Day(NaiveDate::from_ymd_opt(2024, 5, 3).unwrap());
+        storage.save(&page, &[
+            with_id("Pay rent", EntryState::Incomplete, "abcd1111-0000-0000-0000-000000000000"),
+            with_id("Book the dentist", EntryState::Incomplete, "abcd2222-0000-0000-0000-000000000000"),
+        ]).unwrap();
+        for line in [&["done", "abcd"][..], &["schedule", "ABCD", "2024-05-10"], &["done", ""], &["done", "ffff"]] {
+            assert!(matches!(cli(&storage, line), Err(CliError::Usage(_))), "{:?} should have been refused", line);
+        }
+        assert!(storage.load(&page).unwrap().iter().all(|entry| entry.state.is_open()));
+
+        cli(&storage, &["done", "abcd1"]).unwrap();
+        cli(&storage, &["schedule", "ABCD-2222", "2024-05-10"]).unwrap();
+        let states: Vec<String> = storage.load(&page).unwrap().iter().map(|entry| entry.state.to_string()).collect();
+        assert_eq!(states[0], "x");
+        assert!(states[1].starts_with("<2024-05-10"));
+        assert_eq!(contents(&storage, &Page::Day(NaiveDate::from_ymd_opt(2024, 5, 10).unwrap())), vec!["Book the dentist"]);
+    }
+
+    #[test]
+    fn only_open_tasks_can_be_done() {
+        let storage = MemoryStorage::default();
+        let page = Page::Day(NaiveDate::from_ymd_opt(2024, 5, 3).unwrap());
+        storage.save(&page, &[
+            with_id("Dinner with Sam", EntryState::Event, "11111111-0000-0000-0000-000000000000"),
+            with_id("Two are overdue", EntryState::Note, "22222222-0000-0000-0000-000000000000"),
+            with_id("Pay rent", EntryState::Completed, "33333333-0000-0000-0000-000000000000"),
+        ]).unwrap();
+        for id in ["1111", "2222", "3333"] {
+            match cli(&storage, &["done", id]) {
+                Err(CliError::Usage(message)) => assert!(message.contains("isn't an open task"), "{}", message),
+                other => panic!("'done {}' should have been refused, not {:?}", id, other)
+            }
+            assert!(matches!(cli(&storage, &["schedule", id, "tomorrow"]), Err(CliError::Usage(_))));
+        }
+        assert_eq!(storage.list().unwrap(), vec![page]);
+    }
+
+    #[test]
+    fn json_keeps_its_shape() {
+        let storage = MemoryStorage::default();
+        let page = Page::Day(NaiveDate::from_ymd_opt(2024, 5, 3).unwrap());
+        let when = |day| tz::anchor(NaiveDate::from_ymd_opt(2024, 5, day).unwrap());
+        let mut first = with_id("Send the #Invoices @email", EntryState::Incomplete, "11111111-0000-0000-0000-000000000000");
+        first.signifiers.insert(Signifier::Priority);
+        first.signifiers.insert(Signifier::Explore);
+        let mut copy = first.copy(EntryState::Note);
+        copy.depth = 1;
+        storage.save(&page, &[
+            first,
+            copy,
+            Entry::new("Dinner", EntryState::Event),
+            Entry::new("Pay rent", EntryState::Completed),
+            Entry::new("Renew the permit", EntryState::Cancelled),
+            Entry::new("Book the dentist", EntryState::Scheduled(when(10))),
+            Entry::new("Water the plants", EntryState::Collected(when(4))),
+        ]).unwrap();
+        let printed = cli(&storage, &["list", "--date", "2024-05-03", "--format", "json"]).unwrap();
+        let listed: Vec<serde_json::Value> = serde_json::from_str(&printed).unwrap();
+
+        assert_eq!(listed[0], serde_json::json!({
+            "page": {"kind": "day", "date": "2024-05-03"},
+            "id": "11111111-0000-0000-0000-000000000000",
+            "origin": null,
+            "state": "open",
+            "signifiers": ["priority", "explore"],
+            "content": "Send the #Invoices @email",
+            "tags": ["invoices"],
+            "contexts": ["email"],
+            "depth": 0
+        }));
+        assert_eq!(listed[1]["origin"], "11111111-0000-0000-0000-000000000000");
+        assert_eq!(listed[1]["depth"], 1);
+        let states: Vec<(&serde_json::Value, &serde_json::Value)> = listed.iter().map(|entry| (&entry["state"], &entry["date"])).collect();
+        assert_eq!(states, vec![
+            (&serde_json::json!("open"), &serde_json::Value::Null),
+            (&serde_json::json!("note"), &serde_json::Value::Null),
+            (&serde_json::json!("event"), &serde_json::Value::Null),
+            (&serde_json::json!("done"), &serde_json::Value::Null),
+            (&serde_json::json!("cancelled"), &serde_json::Value::Null),
+            (&serde_json::json!("scheduled"), &serde_json::to_value(when(10)).unwrap()),
+            (&serde_json::json!("migrated"), &serde_json::to_value(when(4)).unwrap()),
+        ]);
+        assert_eq!(listed[5]["date"], "2024-05-10T12:00:00Z");
+
+        // The other pages name themselves the same way
+        let pages = [(Page::month_of(NaiveDate::from_ymd_opt(2024, 5, 3).unwrap()), serde_json::json!({"kind": "month", "date": "2024-05-01"})),
+                     (Page::Future, serde_json::json!({"kind": "future"})),
+                     (Page::Collection("Reading list".to_string()), serde_json::json!({"kind": "collection", "name": "Reading list"}))];
+        for (page, expected) in pages {
+            storage.save(&page, &[Entry::new("Dune", EntryState::Note)]).unwrap();
+            let printed = cli(&storage, &["query", "dune", "--format", "ndjson"]).unwrap();
+            let listed: serde_json::Value = serde_json::from_str(printed.lines().next().unwrap()).unwrap();
+            assert_eq!(listed["page"], expected);
+            storage.delete(&page).unwrap();
+        }
+    }
+}
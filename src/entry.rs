@@ -1,10 +1,14 @@
-use chrono::{NaiveDateTime, ParseError as DateParseError};
+use chrono::{DateTime, FixedOffset, NaiveDate, ParseError as DateParseError};
+use serde::Serialize;
+use tz;
+use uuid::Uuid;
+use std::collections::BTreeSet;
 use std::fmt;
 use std::io;
-use std::string::ToString;
 use std::result::Result;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ParseError {
     InvalidTag(String),
     InvalidDateTime(String),
@@ -25,25 +29,45 @@ impl From<io::Error> for ParseError {
     }
 }
 
-impl ToString for ParseError {
-    fn to_string(&self) -> String {
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::ParseError::*;
         match self {
-            InvalidTag(tag) => format!("Invalid entry tag {}", tag),
-            InvalidDateTime(msg) => format!("Invalid DateTime value '{}'", msg),
-            InvalidEntry(entry) => format!("Invalid entry '{}'. Entries should have a valid tag, then a space, then any amount of text", entry),
+            InvalidTag(tag) => write!(f, "Invalid entry tag {}", tag),
+            InvalidDateTime(msg) => write!(f, "Invalid DateTime value '{}'", msg),
+            InvalidEntry(entry) => write!(f, "Invalid entry '{}'. Entries should have a valid tag, then a space, then any amount of text", entry),
         }
     }
 }
 
-#[derive(Debug)]
+/// How the dates embedded in scheduled (<) and collected (>) tags are stored
+const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M%:z";
+
+/// Parse an embedded date. Older versions only stored the day, which is taken to be in the home
+/// timezone.
+fn parse_date(date: &str) -> Result<DateTime<FixedOffset>, ParseError> {
+    match DateTime::parse_from_str(date, DATE_FORMAT) {
+        Ok(date) => Ok(date),
+        Err(_) => Ok(tz::anchor(NaiveDate::parse_from_str(date, "%Y-%m-%d")?))
+    }
+}
+
+/// Serialised as a `state` named like the states of a query, with the `date` of scheduled and
+/// migrated entries alongside it
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", content = "date", rename_all = "lowercase")]
 pub enum EntryState {
+    #[serde(rename = "open")]
     Incomplete,
     Note,
     Event,
-    Scheduled(NaiveDateTime),
-    Collected(NaiveDateTime),
-    Completed
+    Scheduled(DateTime<FixedOffset>),
+    #[serde(rename = "migrated")]
+    Collected(DateTime<FixedOffset>),
+    #[serde(rename = "done")]
+    Completed,
+    /// A task that's no longer relevant. It's closed, but wasn't done.
+    Cancelled
 }
 use self::EntryState::*;
 
@@ -55,10 +79,11 @@ impl EntryState {
             "-" => Ok(Note),
             "o" => Ok(Event),
             "x" => Ok(Completed),
+            "/" => Ok(Cancelled),
             _ => {
                 // Collected and scheduled are harder to handle, as they've an embedded date
                 let slice = tag.get(1..).ok_or_else(|| InvalidTag(tag.to_string()))?;
-                let date = NaiveDateTime::parse_from_str(slice, "%Y-%m-%d")?;
+                let date = parse_date(slice)?;
                 // If we survived the .get above, we won't panic now
                 match tag.get(0..1).expect("If the get before worked how could we possibly fail here?") {
                     "<" => Ok(Scheduled(date)),
@@ -78,9 +103,15 @@ impl EntryState {
             Event => "o",
             Scheduled(_) => "<",
             Collected(_) => ">",
-            Completed => "×"
+            Completed => "×",
+            Cancelled => "•\u{336}"
         }).to_string()
     }
+
+    /// Whether the entry is a task that still needs doing
+    pub fn is_open(&self) -> bool {
+        matches!(self, Incomplete)
+    }
 }
 
 impl fmt::Display for EntryState {
@@ -91,33 +122,163 @@ impl fmt::Display for EntryState {
             Incomplete => write!(f, "."),
             Note => write!(f, "-"),
             Event => write!(f, "o"),
-            Scheduled(date) => write!(f, "{}", date.format("<%Y-%m-%d")),
-            Collected(date) => write!(f, "{}", date.format(">%Y-%m-%d")),
-            Completed => write!(f, "x")
+            Scheduled(date) => write!(f, "<{}", date.format(DATE_FORMAT)),
+            Collected(date) => write!(f, ">{}", date.format(DATE_FORMAT)),
+            Completed => write!(f, "x"),
+            Cancelled => write!(f, "/")
         }
     }
 }
 
 
-#[derive(Debug)]
+/// Rapid-logging signifiers, written in front of the state tag. An entry can have any of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Signifier {
+    Priority,
+    Inspiration,
+    Explore
+}
+
+impl Signifier {
+    pub const ALL: [Signifier; 3] = [Signifier::Priority, Signifier::Inspiration, Signifier::Explore];
+
+    pub fn symbol(self) -> char {
+        match self {
+            Signifier::Priority => '*',
+            Signifier::Inspiration => '!',
+            Signifier::Explore => '?'
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Signifier> {
+        Signifier::ALL.iter().cloned().find(|signifier| signifier.symbol() == symbol)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Signifier::Priority => "priority",
+            Signifier::Inspiration => "inspiration",
+            Signifier::Explore => "explore"
+        }
+    }
+}
+
+/// Strike through text with combining overlays, as cursive has no strikethrough effect
+fn strike(text: &str) -> String {
+    text.chars().flat_map(|c| vec![c, '\u{336}']).collect()
+}
+
+/// The words of `content` starting with `marker`, lowercased and without the marker or any
+/// trailing punctuation. Each word is only listed once.
+pub fn words_marked(content: &str, marker: char) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in content.split_whitespace().filter(|word| word.starts_with(marker)) {
+        let word = word[marker.len_utf8()..]
+            .trim_end_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        if !word.is_empty() && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// How one level of nesting is written before the state tag
+pub const INDENT: &str = "  ";
+
+/// Marks the token after the state tag holding an entry's id
+const ID_MARKER: char = '^';
+/// Marks the token holding the id of the entry this one was copied from
+const ORIGIN_MARKER: char = '~';
+
+/// Parse a token like `^<uuid>`, returning `None` when it isn't one
+fn parse_id(token: &str, marker: char) -> Option<Uuid> {
+    if token.starts_with(marker) {
+        Uuid::parse_str(&token[marker.len_utf8()..]).ok()
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Entry {
+    /// Identifies the entry wherever it ends up, so copies can point back at it
+    pub id: Uuid,
+    /// The entry this one was scheduled or migrated from
+    pub origin: Option<Uuid>,
+    #[serde(flatten)]
     pub state: EntryState,
-    pub content: String
+    pub signifiers: BTreeSet<Signifier>,
+    /// Change with `set_content` so the tags and contexts stay in sync
+    pub content: String,
+    /// The `#tags` in the content, lowercased and without the `#`
+    pub tags: Vec<String>,
+    /// The `@contexts` in the content, lowercased and without the `@`
+    pub contexts: Vec<String>,
+    /// How many levels the entry is nested under the ones before it. 0 for top-level entries.
+    pub depth: usize,
+    /// Children hidden by collapsing the entry in a view. They're still part of the page, and are
+    /// saved right after it.
+    #[serde(skip)]
+    pub folded: Vec<Entry>
 }
 
 impl Entry {
     pub fn new(content: &str, state: EntryState) -> Entry {
-        Entry {state: state,
-               content: content.trim().to_string()}
+        let mut entry = Entry {id: Uuid::new_v4(),
+                               origin: None,
+                               state,
+                               signifiers: BTreeSet::new(),
+                               content: String::new(),
+                               tags: Vec::new(),
+                               contexts: Vec::new(),
+                               depth: 0,
+                               folded: Vec::new()};
+        entry.set_content(content);
+        entry
+    }
+
+    /// Replace the content, picking the tags and contexts out of the new one
+    pub fn set_content(&mut self, content: &str) {
+        self.content = content.trim().to_string();
+        self.tags = words_marked(&self.content, '#');
+        self.contexts = words_marked(&self.content, '@');
+    }
+
+    /// A new entry with the same content and signifiers, pointing back at this one as its origin
+    pub fn copy(&self, state: EntryState) -> Entry {
+        Entry {origin: Some(self.id),
+               signifiers: self.signifiers.clone(),
+               ..Entry::new(&self.content, state)}
     }
 
-    pub fn from_str(line: &str) -> Result<Entry, ParseError> {
+    /// Parse a saved line: its indentation (two spaces or a tab per level), any signifiers, the
+    /// state tag, then the optional `^id` and `~origin` tokens, then the
+    /// content. Lines written before entries had ids are given the one made by `missing_id`.
+    pub fn parse<F: FnOnce() -> Uuid>(line: &str, missing_id: F) -> Result<Entry, ParseError> {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let depth = indent.matches('\t').count() + indent.matches(' ').count() / INDENT.len();
+        let line = line.trim_start();
         let split_idx = line.find(" ");
         match split_idx {
             Some(idx) => {
-                let (tag, body) = line.split_at(idx);
-                let state = self::EntryState::from_str(tag.trim())?;
-                Ok(self::Entry {content: body.trim().to_string(), state: state})
+                let (tag, mut body) = line.split_at(idx);
+                let state_tag = tag.trim_start_matches(|c| Signifier::from_symbol(c).is_some());
+                let signifiers = tag[..tag.len() - state_tag.len()].chars().filter_map(Signifier::from_symbol).collect();
+                let state = self::EntryState::from_str(state_tag)?;
+                body = body.trim_start();
+                let mut next_token = |marker| {
+                    let token = body.split(' ').next().unwrap_or("");
+                    let id = parse_id(token, marker);
+                    if id.is_some() {
+                        body = body[token.len()..].trim_start();
+                    }
+                    id
+                };
+                let id = next_token(ID_MARKER).unwrap_or_else(missing_id);
+                let origin = next_token(ORIGIN_MARKER);
+                Ok(self::Entry {id, origin, state, signifiers, depth, ..Entry::new(body, Incomplete)})
             },
             None => Err(InvalidEntry(line.to_string()))
         }
@@ -131,16 +292,171 @@ impl Entry {
         };
     }
 
+    /// Cancel an open task, or reopen a cancelled one. Returns whether anything changed.
+    pub fn toggle_cancelled(&mut self) -> bool {
+        self.state = match self.state {
+            EntryState::Incomplete => EntryState::Cancelled,
+            EntryState::Cancelled => EntryState::Incomplete,
+            _ => return false
+        };
+        true
+    }
+
     /// Return the nice unicode display symbol, which is a pain to type on the keyboard
-    /// so isn't also used for storage.
+    /// so isn't also used for storage. Cancelled entries are struck through.
     pub fn to_display(&self) -> String {
-        format!("{} {}", self.state.to_display(), self.content)
+        match self.state {
+            EntryState::Cancelled => format!("{} {}", self.state.to_display(), strike(&self.content)),
+            _ => format!("{} {}", self.state.to_display(), self.content)
+        }
+    }
+
+    /// Add the signifier if the entry doesn't have it, otherwise remove it
+    pub fn toggle_signifier(&mut self, signifier: Signifier) {
+        if !self.signifiers.remove(&signifier) {
+            self.signifiers.insert(signifier);
+        }
+    }
+
+    /// The entry's signifier symbols in a fixed order, padded so entries line up
+    pub fn gutter(&self) -> String {
+        Signifier::ALL.iter()
+            .map(|signifier| if self.signifiers.contains(signifier) { signifier.symbol() } else { ' ' })
+            .collect()
+    }
+
+    /// Move the entry and any folded children `levels` levels deeper, or shallower when negative
+    pub fn nest(&mut self, levels: isize) {
+        self.depth = (self.depth as isize + levels).max(0) as usize;
+        for child in self.folded.iter_mut() {
+            child.nest(levels);
+        }
+    }
+
+    /// The entry followed by its folded children, in page order
+    pub fn unfold(mut self) -> Vec<Entry> {
+        let folded = ::std::mem::take(&mut self.folded);
+        let mut entries = vec![self];
+        for child in folded {
+            entries.extend(child.unfold());
+        }
+        entries
     }
 
 }
 
 impl fmt::Display for Entry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {}", self.state, self.content)
+        let signifiers: String = self.signifiers.iter().map(|signifier| signifier.symbol()).collect();
+        write!(f, "{}{}{} {}{}", INDENT.repeat(self.depth), signifiers, self.state, ID_MARKER, self.id.hyphenated())?;
+        if let Some(origin) = self.origin {
+            write!(f, " {}{}", ORIGIN_MARKER, origin.hyphenated())?;
+        }
+        write!(f, " {}", self.content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn ids_are_kept_when_saved() {
+        tz::set_home(tz::Timezone::from_str("+02:00").unwrap());
+        let original = Entry::new("Call the bank", Incomplete);
+        let copy = original.copy(Scheduled(tz::anchor(day(2024, 5, 3))));
+        let line = copy.to_string();
+        assert_eq!(line, format!("<2024-05-03T12:00+02:00 ^{} ~{} Call the bank", copy.id, original.id));
+        let parsed = Entry::parse(&line, Uuid::new_v4).unwrap();
+        assert_eq!(parsed.to_string(), line);
+        assert_eq!(parsed.id, copy.id);
+        assert_eq!(parsed.origin, Some(original.id));
+    }
+
+    #[test]
+    fn every_state_round_trips() {
+        let when = tz::anchor(day(2024, 6, 1));
+        for state in [Incomplete, Note, Event, Completed, Scheduled(when), Collected(when)] {
+            let entry = Entry::new("something", state);
+            assert_eq!(Entry::parse(&entry.to_string(), Uuid::new_v4).unwrap().to_string(), entry.to_string());
+        }
+    }
+
+    #[test]
+    fn old_lines_get_the_missing_id() {
+        let id = Uuid::new_v4();
+        let entry = Entry::parse("<2024-05-03 Pay rent", || id).unwrap();
+        assert_eq!(entry.id, id);
+        assert_eq!(entry.origin, None);
+        assert_eq!(entry.content, "Pay rent");
+        assert_eq!(tz::home().date_of(match entry.state {
+            Scheduled(ref when) => when,
+            _ => panic!("Expected a scheduled entry, got {:?}", entry.state)
+        }), day(2024, 5, 3));
+        // A word that only looks like an id is part of the content
+        let entry = Entry::parse(". ^nope Call back", Uuid::new_v4).unwrap();
+        assert_eq!(entry.content, "^nope Call back");
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        assert!(matches!(Entry::parse("no-space", Uuid::new_v4), Err(InvalidEntry(_))));
+        assert!(matches!(Entry::parse("=2024-05-03 what", Uuid::new_v4), Err(InvalidTag(_))));
+        assert!(matches!(Entry::parse("<tomorrow soon", Uuid::new_v4), Err(InvalidDateTime(_))));
+    }
+
+    #[test]
+    fn indentation_is_the_depth() {
+        let entry = Entry::parse("\t  - Two are overdue", Uuid::new_v4).unwrap();
+        assert_eq!(entry.depth, 2);
+        assert!(entry.to_string().starts_with("    - ^"));
+        let mut parent = Entry::new("Send the invoices", Incomplete);
+        parent.folded = vec![Entry {depth: 1, ..entry}];
+        parent.nest(-3);
+        let unfolded = parent.unfold();
+        assert_eq!(unfolded.iter().map(|entry| entry.depth).collect::<Vec<_>>(), vec![0, 0]);
+        assert_eq!(unfolded[1].content, "Two are overdue");
+    }
+
+    #[test]
+    fn signifiers_come_before_the_state() {
+        let mut entry = Entry::new("Pitch the new logo", Incomplete);
+        entry.toggle_signifier(Signifier::Explore);
+        entry.toggle_signifier(Signifier::Priority);
+        assert_eq!(entry.gutter(), "* ?");
+        let line = entry.to_string();
+        assert!(line.starts_with("*?. ^"));
+        let parsed = Entry::parse(&line, Uuid::new_v4).unwrap();
+        assert_eq!(parsed.signifiers, entry.signifiers);
+        assert_eq!(parsed.to_string(), line);
+        entry.toggle_signifier(Signifier::Priority);
+        assert_eq!(entry.gutter(), "  ?");
+        // Signifiers without a state aren't enough
+        assert!(matches!(Entry::parse("*! Shout", Uuid::new_v4), Err(InvalidTag(_))));
+    }
+
+    #[test]
+    fn cancelling_only_applies_to_open_tasks() {
+        let mut entry = Entry::new("Run", Incomplete);
+        assert!(entry.toggle_cancelled());
+        assert_eq!(entry.to_display(), "•\u{336} R\u{336}u\u{336}n\u{336}");
+        assert!(!entry.state.is_open());
+        let line = entry.to_string();
+        assert!(line.starts_with("/ ^"));
+        assert!(matches!(Entry::parse(&line, Uuid::new_v4).unwrap().state, Cancelled));
+        // Toggling a cancelled task only reopens it
+        entry.toggle_state();
+        assert!(matches!(entry.state, Cancelled));
+        assert!(entry.toggle_cancelled());
+        assert!(entry.state.is_open());
+        let mut note = Entry::new("Just a thought", Note);
+        assert!(!note.toggle_cancelled());
+        assert!(matches!(note.state, Note));
     }
 }
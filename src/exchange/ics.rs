@@ -0,0 +1,307 @@
+//! iCalendar, so events and tasks show up in calendar apps. Events on daily pages become all-day
+//! VEVENTs. Tasks become VTODOs: open ones due on their day, scheduled ones due on the day they
+//! were scheduled for, and done and cancelled ones marked as such. A scheduled task whose copy is
+//! exported too is left to the copy.
+//!
+//! Importing reads the VEVENTs, adding each one as an event on the day it starts. Events with a
+//! start time have it put in front of their summary, in the home timezone when their own zone
+//! isn't one we know. Repeats and alarms aren't followed.
+
+use entry::{Entry, EntryState, Signifier};
+use exchange::{push_entry, ImportError, Pages};
+use page::Page;
+use tz::{self, Timezone};
+
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use uuid::Uuid;
+
+const PRODUCT: &str = "-//bullet-terminal//bullet-terminal//EN";
+
+/// Lines longer than this many bytes are folded onto the next
+const LINE_LENGTH: usize = 75;
+
+/// Escape text for a property value
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => ()
+        }
+    }
+    unescaped
+}
+
+/// Write a content line, folding it so no line is longer than `LINE_LENGTH` bytes
+fn push_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > LINE_LENGTH {
+            out.push_str("\r\n ");
+            // The space starting the continuation counts
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn date_value(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+/// The properties every component has
+fn common(out: &mut String, entry: &Entry, stamp: &str) {
+    push_line(out, &format!("UID:{}@bullet-terminal", entry.id));
+    push_line(out, &format!("DTSTAMP:{}", stamp));
+    push_line(out, &format!("SUMMARY:{}", escape(&entry.content)));
+    if !entry.tags.is_empty() {
+        let tags: Vec<String> = entry.tags.iter().map(|tag| escape(tag)).collect();
+        push_line(out, &format!("CATEGORIES:{}", tags.join(",")));
+    }
+    if entry.signifiers.contains(&Signifier::Priority) {
+        push_line(out, "PRIORITY:1");
+    }
+}
+
+/// The day a task is due, and what state it's in, or `None` for entries that aren't exported as
+/// tasks
+fn todo(page: &Page, entry: &Entry) -> Option<(Option<NaiveDate>, &'static str)> {
+    let day = match page {
+        Page::Day(day) => Some(*day),
+        _ => None
+    };
+    match entry.state {
+        EntryState::Incomplete => Some((day, "NEEDS-ACTION")),
+        EntryState::Scheduled(ref when) => Some((Some(tz::home().date_of(when)), "NEEDS-ACTION")),
+        EntryState::Completed => Some((day, "COMPLETED")),
+        EntryState::Cancelled => Some((day, "CANCELLED")),
+        EntryState::Collected(_) | EntryState::Event | EntryState::Note => None,
+    }
+}
+
+pub fn export(pages: &[(Page, Vec<Entry>)]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let copied: BTreeSet<Uuid> = pages.iter()
+        .flat_map(|(_, entries)| entries.iter().filter_map(|entry| entry.origin))
+        .collect();
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{}", PRODUCT));
+    for (page, entries) in pages {
+        for entry in entries {
+            if let (Page::Day(day), EntryState::Event) = (page, &entry.state) {
+                push_line(&mut out, "BEGIN:VEVENT");
+                common(&mut out, entry, &stamp);
+                push_line(&mut out, &format!("DTSTART;VALUE=DATE:{}", date_value(*day)));
+                push_line(&mut out, &format!("DTEND;VALUE=DATE:{}", date_value(*day + Duration::days(1))));
+                push_line(&mut out, "END:VEVENT");
+                continue;
+            }
+            let (due, status) = match todo(page, entry) {
+                Some(todo) => todo,
+                None => continue
+            };
+            if let EntryState::Scheduled(_) = entry.state {
+                if copied.contains(&entry.id) {
+                    continue;
+                }
+            }
+            push_line(&mut out, "BEGIN:VTODO");
+            common(&mut out, entry, &stamp);
+            if let Some(due) = due {
+                push_line(&mut out, &format!("DUE;VALUE=DATE:{}", date_value(due)));
+            }
+            push_line(&mut out, &format!("STATUS:{}", status));
+            push_line(&mut out, "END:VTODO");
+        }
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// A content line split into its name, parameters and value
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn parse(line: &str) -> Option<Property> {
+        let colon = line.find(':')?;
+        let mut head = line[..colon].split(';');
+        let name = head.next()?.trim().to_uppercase();
+        let params = head.filter_map(|param| {
+            let mut parts = param.splitn(2, '=');
+            Some((parts.next()?.trim().to_uppercase(), parts.next()?.trim_matches('"').to_string()))
+        }).collect();
+        Some(Property {name, params, value: line[colon + 1..].trim().to_string()})
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(param, _)| param == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Join folded lines back together, keeping the number of the first line of each
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        match (line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')), lines.last_mut()) {
+            (Some(rest), Some((_, last))) => last.push_str(rest),
+            _ => lines.push((idx + 1, line.to_string()))
+        }
+    }
+    lines
+}
+
+/// The day an event starts on in the home timezone, and the time it starts at if it has one
+fn start(property: &Property) -> Result<(NaiveDate, Option<String>), String> {
+    let value = property.value.as_str();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Ok((date, None));
+    }
+    let invalid = || format!("'{}' isn't a date or a time", value);
+    let (local, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false)
+    };
+    let naive = NaiveDateTime::parse_from_str(local, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+    let when = match (utc, property.param("TZID")) {
+        (true, _) => DateTime::<Utc>::from_naive_utc_and_offset(naive, Utc).fixed_offset(),
+        (false, zone) => match zone.map(Timezone::from_str) {
+            Some(Ok(zone)) => zone.at(naive.date(), naive.time()),
+            // Floating times happen at that time wherever you are. Zones we don't know, like the
+            // Windows names Outlook uses, are taken to be floating too rather than losing the event.
+            _ => tz::home().at(naive.date(), naive.time()),
+        },
+    };
+    let local = tz::home().local_of(&when);
+    Ok((local.date(), Some(local.format("%H:%M").to_string())))
+}
+
+/// A VEVENT being read
+#[derive(Default)]
+struct Event {
+    /// The line it begins on
+    line: usize,
+    start: Option<(NaiveDate, Option<String>)>,
+    summary: String,
+    /// Whether we're inside one of its VALARMs, whose properties aren't the event's
+    in_alarm: bool,
+}
+
+/// The events of an iCalendar file, each on the daily page of the day it starts
+pub fn import(text: &str) -> Result<Pages, ImportError> {
+    let mut pages = Pages::new();
+    let mut event: Option<Event> = None;
+    for (number, line) in unfold(text) {
+        let error = |message| ImportError {line: number, message};
+        let property = match Property::parse(&line) {
+            Some(property) => property,
+            None if line.trim().is_empty() => continue,
+            None => return Err(error(format!("'{}' isn't a property", line)))
+        };
+        match (property.name.as_str(), property.value.to_uppercase().as_str(), event.as_mut()) {
+            ("BEGIN", "VEVENT", None) => event = Some(Event {line: number, ..Event::default()}),
+            ("BEGIN", "VEVENT", Some(_)) => return Err(error("Events can't be inside other events".to_string())),
+            ("BEGIN", "VALARM", Some(event)) => event.in_alarm = true,
+            ("END", "VALARM", Some(event)) => event.in_alarm = false,
+            (_, _, Some(event)) if event.in_alarm => (),
+            ("DTSTART", _, Some(event)) => event.start = Some(start(&property).map_err(error)?),
+            ("SUMMARY", _, Some(event)) => event.summary = unescape(&property.value),
+            ("END", "VEVENT", Some(_)) => {
+                let event = event.take().expect("The event was just matched");
+                let (day, time) = match event.start {
+                    Some(start) => start,
+                    None => return Err(ImportError {line: event.line, message: "The event has no start".to_string()})
+                };
+                let content = match time {
+                    Some(time) => format!("{} {}", time, event.summary.trim()),
+                    None => event.summary.trim().to_string()
+                };
+                if !content.is_empty() {
+                    push_entry(&mut pages, &Page::Day(day), Entry::new(&content, EntryState::Event));
+                }
+            }
+            _ => ()
+        }
+    }
+    match event {
+        Some(event) => Err(ImportError {line: event.line, message: "The event never ends".to_string()}),
+        None => Ok(pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange::tests::{day, lines, save, stored};
+    use storage::MemoryStorage;
+
+    fn event(properties: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n{}END:VEVENT\r\nEND:VCALENDAR\r\n", properties)
+    }
+
+    fn imported(text: &str) -> Vec<(Page, Vec<String>)> {
+        lines(&import(text).unwrap())
+    }
+
+    #[test]
+    fn exported_events_import_the_same() {
+        let storage = MemoryStorage::default();
+        let long = "A very long event name that has to be folded onto a second line, with commas; and semicolons";
+        save(&storage, &Page::Day(day(2024, 5, 3)), &[&format!("o {}", long), ". Tasks aren't imported", "o Dinner"]);
+        save(&storage, &Page::Day(day(2024, 5, 4)), &["o Brunch"]);
+        let exported = export(&stored(&storage));
+        assert!(exported.lines().all(|line| line.len() <= LINE_LENGTH));
+        assert_eq!(imported(&exported), vec![
+            (Page::Day(day(2024, 5, 3)), vec![format!("o {}", long), "o Dinner".to_string()]),
+            (Page::Day(day(2024, 5, 4)), vec!["o Brunch".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn times_are_moved_to_the_home_timezone() {
+        tz::set_home(Timezone::from_str("+02:00").unwrap());
+        assert_eq!(imported(&event("DTSTART:20240503T230000Z\r\nSUMMARY:Launch\r\n")),
+                   vec![(Page::Day(day(2024, 5, 4)), vec!["o 01:00 Launch".to_string()])]);
+        assert_eq!(imported(&event("DTSTART;TZID=America/New_York:20240503T090000\r\nSUMMARY:Standup\r\n")),
+                   vec![(Page::Day(day(2024, 5, 3)), vec!["o 15:00 Standup".to_string()])]);
+        // Floating times and zones we don't know stay as they are
+        assert_eq!(imported(&event("DTSTART:20240503T090000\r\nSUMMARY:Gym\r\n")),
+                   vec![(Page::Day(day(2024, 5, 3)), vec!["o 09:00 Gym".to_string()])]);
+        assert_eq!(imported(&event("DTSTART;TZID=\"Pacific Standard Time\":20240503T090000\r\nSUMMARY:Sync\r\n")),
+                   vec![(Page::Day(day(2024, 5, 3)), vec!["o 09:00 Sync".to_string()])]);
+    }
+
+    #[test]
+    fn alarms_are_ignored() {
+        let text = event("DTSTART;VALUE=DATE:20240503\r\nSUMMARY:Flight\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\n\
+                          SUMMARY:Reminder\r\nDTSTART:20240502T000000Z\r\nEND:VALARM\r\n");
+        assert_eq!(imported(&text), vec![(Page::Day(day(2024, 5, 3)), vec!["o Flight".to_string()])]);
+    }
+
+    #[test]
+    fn broken_events_are_errors() {
+        assert_eq!(import(&event("SUMMARY:Nowhen\r\n")).unwrap_err().line, 2);
+        assert_eq!(import("BEGIN:VEVENT\r\nDTSTART:20240503\r\n").unwrap_err().line, 1);
+        assert_eq!(import(&event("DTSTART:May 3rd\r\n")).unwrap_err().line, 3);
+        assert_eq!(import("BEGIN:VCALENDAR\r\nnonsense\r\n").unwrap_err().line, 2);
+    }
+}
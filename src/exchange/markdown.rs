@@ -0,0 +1,250 @@
+//! Markdown, for sharing pages in pull requests, wikis and chat. Each page is a `#` heading with
+//! its title, followed by a list of its entries:
+//!
+//! - `- [ ]` open tasks, `- [x]` done ones and `- [-]` cancelled ones
+//! - `- [<2024-05-03]` scheduled and `- [>2024-05-03]` migrated tasks, with the day they went to
+//! - `- ○` events
+//! - plain `-` bullets for notes
+//!
+//! Signifiers follow in backticks (`` `*!` ``), and nested entries are indented by two spaces a
+//! level. Content that would be read as one of those markers, like a note starting with `[ ]`, is
+//! written with a backslash in front. Importing also takes `*` and `+` bullets, and any other text as notes. Only lines
+//! CommonMark reads as headings (one to six `#` and a space) start a page, so a line like
+//! `#work/home` is a note.
+
+use entry::{Entry, EntryState, Signifier, INDENT};
+use exchange::{clamp_depth, push_entry, ImportError, Pages};
+use page::Page;
+use tz;
+
+use std::fmt::Write;
+
+use chrono::NaiveDate;
+
+/// Marks events, which have no Markdown equivalent
+const EVENT: &str = "○";
+
+/// The content, with a backslash in front when it would be read as a state marker or signifiers
+fn escaped(content: &str) -> String {
+    if content.starts_with(['[', '`', '\\']) || content.starts_with(EVENT) {
+        format!("\\{}", content)
+    } else {
+        content.to_string()
+    }
+}
+
+/// The list marker for an entry's state
+fn marker(state: &EntryState) -> String {
+    let day = |when| tz::home().date_of(when).format("%Y-%m-%d");
+    match state {
+        EntryState::Incomplete => "[ ] ".to_string(),
+        EntryState::Completed => "[x] ".to_string(),
+        EntryState::Cancelled => "[-] ".to_string(),
+        EntryState::Scheduled(when) => format!("[<{}] ", day(when)),
+        EntryState::Collected(when) => format!("[>{}] ", day(when)),
+        EntryState::Event => format!("{} ", EVENT),
+        EntryState::Note => String::new(),
+    }
+}
+
+pub fn export(pages: &[(Page, Vec<Entry>)]) -> String {
+    let mut out = String::new();
+    for (idx, (page, entries)) in pages.iter().enumerate() {
+        if idx > 0 {
+            out.push('\n');
+        }
+        writeln!(out, "# {}\n", page).expect("Writing to a string can't fail");
+        for entry in entries {
+            let signifiers: String = entry.signifiers.iter().map(|signifier| signifier.symbol()).collect();
+            let signifiers = if signifiers.is_empty() { signifiers } else { format!("`{}` ", signifiers) };
+            writeln!(out, "{}- {}{}{}", INDENT.repeat(entry.depth), marker(&entry.state), signifiers, escaped(&entry.content))
+                .expect("Writing to a string can't fail");
+        }
+    }
+    out
+}
+
+/// Split the state marker off a list item, leaving the rest of it
+fn parse_marker(item: &str) -> Result<(EntryState, &str), String> {
+    let dated = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map(tz::anchor)
+        .map_err(|_| format!("'{}' isn't a date. Dates look like 2024-05-03", text));
+    if let Some(rest) = item.strip_prefix(EVENT) {
+        return Ok((EntryState::Event, rest));
+    }
+    let (checkbox, rest) = match (item.strip_prefix('['), item.find(']')) {
+        (Some(_), Some(end)) => (&item[1..end], &item[end + 1..]),
+        _ => return Ok((EntryState::Note, item))
+    };
+    let state = match checkbox {
+        " " => EntryState::Incomplete,
+        "x" | "X" => EntryState::Completed,
+        "-" => EntryState::Cancelled,
+        _ if checkbox.starts_with('<') => EntryState::Scheduled(dated(&checkbox[1..])?),
+        _ if checkbox.starts_with('>') => EntryState::Collected(dated(&checkbox[1..])?),
+        // Not a checkbox, just a note starting with brackets
+        _ => return Ok((EntryState::Note, item))
+    };
+    Ok((state, rest))
+}
+
+/// Parse a list item, without its indentation and bullet
+fn parse_item(item: &str, depth: usize) -> Result<Entry, String> {
+    let (state, rest) = parse_marker(item)?;
+    let mut rest = rest.trim_start();
+    let mut signifiers = Vec::new();
+    if let Some(quoted) = rest.strip_prefix('`') {
+        if let Some(end) = quoted.find('`') {
+            let symbols: Option<Vec<Signifier>> = quoted[..end].chars().map(Signifier::from_symbol).collect();
+            if let Some(symbols) = symbols.filter(|symbols| !symbols.is_empty()) {
+                signifiers = symbols;
+                rest = quoted[end + 1..].trim_start();
+            }
+        }
+    }
+    let rest = rest.strip_prefix('\\').unwrap_or(rest);
+    let mut entry = Entry::new(rest, state);
+    entry.signifiers = signifiers.into_iter().collect();
+    entry.depth = depth;
+    Ok(entry)
+}
+
+/// The text of a line that's an ATX heading as CommonMark has them: indented by up to three
+/// spaces, then one to six `#` followed by a space or the end of the line
+fn heading(line: &str) -> Option<&str> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let marked = &line[indent..];
+    let level = marked.len() - marked.trim_start_matches('#').len();
+    let text = &marked[level..];
+    if indent <= 3 && (1..=6).contains(&level) && (text.is_empty() || text.starts_with([' ', '\t'])) {
+        Some(text)
+    } else {
+        None
+    }
+}
+
+/// The entries of a Markdown document. Entries before the first heading go on `default`.
+pub fn import(text: &str, default: &Page) -> Result<Pages, ImportError> {
+    let mut pages = Pages::new();
+    let mut page = default.clone();
+    for (idx, line) in text.lines().enumerate() {
+        let error = |message| ImportError {line: idx + 1, message};
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Some(title) = heading(line) {
+            page = Page::from_title(title).map_err(error)?;
+            continue;
+        }
+        let indent = &line[..line.len() - trimmed.len()];
+        let depth = indent.chars().filter(|c| *c == '\t').count() + indent.chars().filter(|c| *c == ' ').count() / INDENT.len();
+        let entry = match ["- ", "* ", "+ "].iter().find_map(|bullet| trimmed.strip_prefix(bullet)) {
+            Some(item) => parse_item(item, depth).map_err(error)?,
+            None => Entry::new(trimmed, EntryState::Note)
+        };
+        if entry.content.is_empty() {
+            continue;
+        }
+        let previous = pages.iter().find(|(existing, _)| *existing == page).and_then(|(_, entries)| entries.last());
+        let depth = clamp_depth(entry.depth, previous);
+        push_entry(&mut pages, &page, Entry {depth, ..entry});
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange::tests::{day, lines, save, stored};
+    use storage::MemoryStorage;
+
+    #[test]
+    fn exported_pages_import_the_same() {
+        tz::set_home(tz::Timezone::from_str("+00:00").unwrap());
+        let storage = MemoryStorage::default();
+        save(&storage, &Page::Day(day(2024, 5, 3)), &[
+            "*. Send the invoices #work @email",
+            "  - Two are overdue",
+            "    !?x Ask about the late fee",
+            "o Dinner with Sam",
+            "/ Renew the parking permit",
+            "<2024-06-01T12:00+00:00 Book the dentist",
+            ">2024-05-04T12:00+00:00 Water the plants",
+        ]);
+        save(&storage, &Page::month_of(day(2024, 5, 1)), &[". Taxes"]);
+        save(&storage, &Page::Future, &["o Conference"]);
+        save(&storage, &Page::Collection("Reading list".to_string()), &["- [Dune] by Frank Herbert"]);
+        let pages = stored(&storage);
+        let imported = import(&export(&pages), &Page::Future).unwrap();
+        assert_eq!(lines(&imported), lines(&pages));
+    }
+
+    #[test]
+    fn other_markdown_is_read_as_well_as_it_can_be() {
+        let text = "Some notes first\n\n## 2024-05-03\n\n* [X] Done\n+ Plain\n        - Too deep\n[Not a checkbox]\n";
+        let imported = import(text, &Page::Future).unwrap();
+        assert_eq!(lines(&imported), vec![
+            (Page::Future, vec!["- Some notes first".to_string()]),
+            (Page::Day(day(2024, 5, 3)), vec![
+                "x Done".to_string(),
+                "- Plain".to_string(),
+                "  - Too deep".to_string(),
+                "- [Not a checkbox]".to_string(),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn only_commonmark_headings_start_pages() {
+        let text = "#work/home first
+### 2024-05-03
+#hashtag
+####### Seven is too many
+    # Code
+- Note
+";
+        let imported = import(text, &Page::Future).unwrap();
+        assert_eq!(lines(&imported), vec![
+            (Page::Future, vec!["- #work/home first".to_string()]),
+            (Page::Day(day(2024, 5, 3)), vec![
+                "- #hashtag".to_string(),
+                "- ####### Seven is too many".to_string(),
+                "- # Code".to_string(),
+                "- Note".to_string(),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn content_that_looks_like_a_marker_is_escaped() {
+        tz::set_home(tz::Timezone::from_str("+00:00").unwrap());
+        let storage = MemoryStorage::default();
+        let entries = [
+            "- [ ] is how Markdown writes a checkbox",
+            ". [x] marks the spot",
+            "*- `*` is the priority signifier",
+            "- ○ is how events are marked",
+            "- \\ starts an escape",
+            "- Nothing to escape in [brackets]",
+        ];
+        save(&storage, &Page::Future, &entries);
+        let exported = export(&stored(&storage));
+        assert!(exported.contains("- \\[ ] is how Markdown writes a checkbox\n"), "{}", exported);
+        assert!(exported.contains("- [ ] \\[x] marks the spot\n"), "{}", exported);
+        assert!(exported.contains("- `*` \\`*` is the priority signifier\n"), "{}", exported);
+        assert!(exported.contains("- Nothing to escape in [brackets]\n"), "{}", exported);
+        let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+        assert_eq!(lines(&import(&exported, &Page::Collection("Inbox".to_string())).unwrap()),
+                   vec![(Page::Future, entries)]);
+    }
+
+    #[test]
+    fn headings_have_to_be_usable_titles() {
+        for text in ["# ../../escaped\n- Note", "- Note\n## Install / Usage", "#\n- Note"] {
+            assert!(import(text, &Page::Future).is_err(), "'{}' should have been refused", text);
+        }
+        let error = import("# Fine\n\n- [<someday] Later", &Page::Future).unwrap_err();
+        assert_eq!(error.line, 3);
+    }
+}
@@ -0,0 +1,84 @@
+//! Reading and writing pages in formats other tools understand. Every format exports pages with
+//! their entries and imports them back as new entries, which are added to the end of their pages.
+
+pub mod ics;
+pub mod markdown;
+pub mod org;
+pub mod todotxt;
+
+use entry::Entry;
+use page::Page;
+
+use std::fmt;
+
+/// Pages with their entries, in the order they're written out
+pub type Pages = Vec<(Page, Vec<Entry>)>;
+
+#[derive(Debug)]
+pub struct ImportError {
+    /// Counted from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Add an entry to the page, keeping pages in the order they were first seen
+pub fn push_entry(pages: &mut Pages, page: &Page, entry: Entry) {
+    match pages.iter_mut().find(|(existing, _)| existing == page) {
+        Some((_, entries)) => entries.push(entry),
+        None => pages.push((page.clone(), vec![entry]))
+    }
+}
+
+/// How deep an entry can be nested after `previous`: at most one level deeper than it
+pub fn clamp_depth(depth: usize, previous: Option<&Entry>) -> usize {
+    depth.min(previous.map_or(0, |entry| entry.depth + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entry::INDENT;
+    use storage::{MemoryStorage, Storage};
+
+    use chrono::NaiveDate;
+    use uuid::Uuid;
+
+    pub fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Save entries written the way they're saved, without their ids
+    pub fn save(storage: &MemoryStorage, page: &Page, lines: &[&str]) {
+        let entries: Vec<Entry> = lines.iter().map(|line| Entry::parse(line, Uuid::new_v4).unwrap()).collect();
+        storage.save(page, &entries).unwrap();
+    }
+
+    /// Every page in the storage, as they're exported
+    pub fn stored(storage: &MemoryStorage) -> Pages {
+        storage.list().unwrap().into_iter()
+            .map(|page| {
+                let entries = storage.load(&page).unwrap();
+                (page, entries)
+            })
+            .collect()
+    }
+
+    /// The pages with their entries the way they're saved, leaving out the ids importing makes up
+    pub fn lines(pages: &Pages) -> Vec<(Page, Vec<String>)> {
+        pages.iter()
+            .map(|(page, entries)| {
+                let lines = entries.iter().map(|entry| {
+                    let signifiers: String = entry.signifiers.iter().map(|signifier| signifier.symbol()).collect();
+                    format!("{}{}{} {}", INDENT.repeat(entry.depth), signifiers, entry.state, entry.content)
+                }).collect();
+                (page.clone(), lines)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,339 @@
+//! Org-mode, for reading and planning the journal from Emacs. Each page is a top-level headline
+//! with its title, and its entries follow:
+//!
+//! - tasks are headlines one level down, marked `TODO`, `DONE` or `CANCELLED`
+//! - scheduled tasks are `TODO` headlines with a `SCHEDULED:` line, so they show up in the agenda,
+//!   unless their copy is exported too. Importing one also adds an open copy to the day.
+//! - migrated tasks are `MIGRATED` headlines with the day they went to, like `[2024-06-01 Sat]`
+//! - events are headlines without a keyword, with their day when they're on a daily page
+//! - notes are plain list items
+//!
+//! Nested entries are a level deeper, and notes are indented by two spaces a level. The priority
+//! signifier is the priority cookie `[#A]`. Importing also takes checkbox items as tasks, trailing
+//! `:tags:` as `#tags`, any other text as notes, and top-level headlines that are a timestamp as
+//! the day's page.
+//!
+//! Entries starting with something Org would read as markup, like an event starting with `TODO`,
+//! are written with a zero-width space in front, as the Org manual suggests, which importing takes
+//! off again.
+
+use entry::{Entry, EntryState, Signifier, INDENT};
+use exchange::{clamp_depth, push_entry, ImportError, Pages};
+use page::Page;
+use tz;
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+/// Lets Org know which keywords mark tasks, and which of them are finished
+const KEYWORDS: &str = "#+TODO: TODO | DONE CANCELLED MIGRATED";
+
+/// Goes in front of content that would otherwise be read as markup
+const ESCAPE: char = '\u{200B}';
+
+fn is_keyword(word: &str) -> bool {
+    matches!(word, "TODO" | "DONE" | "CANCELLED" | "MIGRATED")
+}
+
+/// Whether the text starts with a priority cookie like `[#A]`
+fn has_priority(text: &str) -> bool {
+    text.starts_with("[#") && text.get(3..4) == Some("]")
+}
+
+/// Whether the text starts with a checkbox like `[ ]`
+fn has_checkbox(text: &str) -> bool {
+    matches!(text.get(..4), Some("[ ] ") | Some("[X] ") | Some("[x] "))
+}
+
+/// The content, escaped when it starts with a keyword, a priority cookie or a checkbox
+fn escaped(content: &str) -> String {
+    let first = content.split(' ').next().unwrap_or("");
+    if is_keyword(first) || has_priority(content) || has_checkbox(content) || content.starts_with(ESCAPE) {
+        format!("{}{}", ESCAPE, content)
+    } else {
+        content.to_string()
+    }
+}
+
+/// A day as an Org timestamp, active ones showing up in the agenda
+fn timestamp(day: NaiveDate, active: bool) -> String {
+    let (open, close) = if active { ('<', '>') } else { ('[', ']') };
+    format!("{}{}{}", open, day.format("%Y-%m-%d %a"), close)
+}
+
+pub fn export(pages: &[(Page, Vec<Entry>)]) -> String {
+    let copied: BTreeSet<Uuid> = pages.iter()
+        .flat_map(|(_, entries)| entries.iter().filter_map(|entry| entry.origin))
+        .collect();
+    let mut out = format!("{}\n", KEYWORDS);
+    for (page, entries) in pages {
+        writeln!(out, "\n* {}", page).expect("Writing to a string can't fail");
+        for entry in entries {
+            if let EntryState::Scheduled(_) = entry.state {
+                if copied.contains(&entry.id) {
+                    continue;
+                }
+            }
+            let stars = "*".repeat(entry.depth + 2);
+            let priority = if entry.signifiers.contains(&Signifier::Priority) { "[#A] " } else { "" };
+            let content = escaped(&entry.content);
+            let line = match entry.state {
+                EntryState::Note => format!("{}- {}", INDENT.repeat(entry.depth), content),
+                EntryState::Event => match page {
+                    Page::Day(day) => format!("{} {}{}\n{}", stars, priority, content, timestamp(*day, true)),
+                    _ => format!("{} {}{}", stars, priority, content),
+                },
+                EntryState::Incomplete => format!("{} TODO {}{}", stars, priority, content),
+                EntryState::Completed => format!("{} DONE {}{}", stars, priority, content),
+                EntryState::Cancelled => format!("{} CANCELLED {}{}", stars, priority, content),
+                EntryState::Scheduled(ref when) => format!("{} TODO {}{}\nSCHEDULED: {}", stars, priority, content,
+                                                           timestamp(tz::home().date_of(when), true)),
+                EntryState::Collected(ref when) => format!("{} MIGRATED {}{} {}", stars, priority,
+                                                           timestamp(tz::home().date_of(when), false), content),
+            };
+            writeln!(out, "{}", line).expect("Writing to a string can't fail");
+        }
+    }
+    out
+}
+
+/// The day in a timestamp like `<2024-05-03 Fri>` or `[2024-05-03 Fri 10:00]`, along with the
+/// text after it. `None` when the text doesn't start with one.
+fn parse_timestamp(text: &str) -> Option<(NaiveDate, &str)> {
+    let close = match text.chars().next()? {
+        '<' => '>',
+        '[' => ']',
+        _ => return None
+    };
+    let end = text.find(close)?;
+    let day = NaiveDate::parse_from_str(text[1..end].split_whitespace().next()?, "%Y-%m-%d").ok()?;
+    Some((day, text[end + 1..].trim_start()))
+}
+
+/// The page a top-level headline is the title of. Headlines that would make an unusable collection
+/// name are refused.
+fn headline_page(title: &str) -> Result<Page, String> {
+    match parse_timestamp(title.trim()) {
+        Some((day, "")) => Ok(Page::Day(day)),
+        _ => Page::from_title(title)
+    }
+}
+
+/// Move trailing Org tags like `:work:home:` into the content as `#work #home`
+fn with_tags(content: &str) -> String {
+    let content = content.trim_end();
+    let (rest, last) = match content.rfind(' ') {
+        Some(idx) => (&content[..idx], &content[idx + 1..]),
+        None => return content.to_string()
+    };
+    let tags: Vec<&str> = last.split(':').collect();
+    let is_tags = last.len() > 2 && last.starts_with(':') && last.ends_with(':')
+        && tags[1..tags.len() - 1].iter().all(|tag| !tag.is_empty() && tag.chars().all(|c| c.is_alphanumeric() || c == '_'));
+    if !is_tags {
+        return content.to_string();
+    }
+    let tags: Vec<String> = tags[1..tags.len() - 1].iter().map(|tag| format!("#{}", tag)).collect();
+    format!("{} {}", rest.trim_end(), tags.join(" "))
+}
+
+/// Parse a headline below the top level, without its stars
+fn parse_headline(headline: &str, depth: usize) -> Result<Entry, String> {
+    let (keyword, rest) = match headline.find(' ') {
+        Some(idx) => (&headline[..idx], headline[idx + 1..].trim_start()),
+        None => (headline, "")
+    };
+    let keyword = if is_keyword(keyword) { Some(keyword) } else { None };
+    let mut rest = if keyword.is_some() { rest } else { headline };
+    let priority = has_priority(rest);
+    if priority {
+        rest = rest[4..].trim_start();
+    }
+    let state = match keyword {
+        Some("TODO") => EntryState::Incomplete,
+        Some("DONE") => EntryState::Completed,
+        Some("CANCELLED") => EntryState::Cancelled,
+        Some(_) => match parse_timestamp(rest) {
+            Some((day, after)) => {
+                rest = after;
+                EntryState::Collected(tz::anchor(day))
+            }
+            None => return Err("Migrated tasks need the day they went to, like [2024-06-01 Sat]".to_string())
+        },
+        None => EntryState::Event
+    };
+    let rest = rest.strip_prefix(ESCAPE).unwrap_or(rest);
+    let mut entry = Entry::new(&with_tags(rest), state);
+    if priority {
+        entry.signifiers.insert(Signifier::Priority);
+    }
+    entry.depth = depth;
+    Ok(entry)
+}
+
+/// Parse a list item, without its indentation and bullet
+fn parse_item(item: &str, depth: usize) -> Entry {
+    let (state, rest) = match item.get(..4) {
+        Some("[ ] ") => (EntryState::Incomplete, &item[4..]),
+        Some("[X] ") | Some("[x] ") => (EntryState::Completed, &item[4..]),
+        _ => (EntryState::Note, item)
+    };
+    let mut entry = Entry::new(rest.strip_prefix(ESCAPE).unwrap_or(rest), state);
+    entry.depth = depth;
+    entry
+}
+
+/// Lines that only say something about the headline above them, which the journal has no place
+/// for
+fn is_planning(line: &str) -> bool {
+    // Drawers and their properties, like `:PROPERTIES:` and `:ID: 1234`
+    let drawer = line.split_whitespace().next().is_some_and(|first| first.len() > 1 && first.starts_with(':') && first.ends_with(':'));
+    drawer || line.starts_with("CLOSED:") || line.starts_with("DEADLINE:")
+        // Comments and keywords like `#+TITLE:`
+        || line.starts_with("# ") || line.starts_with("#+")
+        || parse_timestamp(line).is_some_and(|(_, rest)| rest.is_empty())
+}
+
+/// The entries of an Org document. Entries before the first top-level headline go on `default`.
+pub fn import(text: &str, default: &Page) -> Result<Pages, ImportError> {
+    let mut pages = Pages::new();
+    let mut page = default.clone();
+    for (idx, line) in text.lines().enumerate() {
+        let error = |message| ImportError {line: idx + 1, message};
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let stars = line.len() - line.trim_start_matches('*').len();
+        let entry = if stars > 0 && line[stars..].starts_with(' ') {
+            let headline = line[stars..].trim();
+            if stars == 1 {
+                page = headline_page(headline).map_err(error)?;
+                continue;
+            }
+            parse_headline(headline, stars - 2).map_err(error)?
+        } else if let Some(scheduled) = trimmed.strip_prefix("SCHEDULED:") {
+            let day = match parse_timestamp(scheduled.trim_start()) {
+                Some((day, _)) => day,
+                None => return Err(error(format!("'{}' isn't a timestamp", scheduled.trim())))
+            };
+            // Scheduling only means something for the open task it's under, which gets an open copy
+            // on the day like it does with <
+            let last = pages.iter_mut().find(|(existing, _)| *existing == page).and_then(|(_, entries)| entries.last_mut());
+            if let Some(task) = last.filter(|entry| matches!(entry.state, EntryState::Incomplete)) {
+                task.state = EntryState::Scheduled(tz::anchor(day));
+                let copy = Entry {depth: 0, ..task.copy(EntryState::Incomplete)};
+                push_entry(&mut pages, &Page::Day(day), copy);
+            }
+            continue;
+        } else if is_planning(trimmed) {
+            continue;
+        } else {
+            let indent = &line[..line.len() - trimmed.len()];
+            let depth = indent.chars().filter(|c| *c == '\t').count() + indent.chars().filter(|c| *c == ' ').count() / INDENT.len();
+            match ["- ", "+ ", "* "].iter().find_map(|bullet| trimmed.strip_prefix(bullet)) {
+                Some(item) => parse_item(item, depth),
+                None => Entry {depth, ..Entry::new(trimmed, EntryState::Note)}
+            }
+        };
+        if entry.content.is_empty() {
+            continue;
+        }
+        let previous = pages.iter().find(|(existing, _)| *existing == page).and_then(|(_, entries)| entries.last());
+        let depth = clamp_depth(entry.depth, previous);
+        push_entry(&mut pages, &page, Entry {depth, ..entry});
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange::tests::{day, lines, save, stored};
+    use storage::MemoryStorage;
+
+    #[test]
+    fn exported_pages_import_the_same() {
+        tz::set_home(tz::Timezone::from_str("+00:00").unwrap());
+        let storage = MemoryStorage::default();
+        save(&storage, &Page::Day(day(2024, 5, 3)), &[
+            "*. Send the invoices #work",
+            "  - Two are overdue",
+            "    x Ask about the late fee",
+            "o Dinner with Sam",
+            "/ Renew the parking permit",
+            "<2024-06-01T12:00+00:00 Book the dentist",
+            ">2024-05-04T12:00+00:00 Water the plants",
+        ]);
+        save(&storage, &Page::month_of(day(2024, 5, 1)), &[". Taxes"]);
+        save(&storage, &Page::Future, &["o Conference"]);
+        save(&storage, &Page::Collection("Reading list".to_string()), &["- Dune"]);
+        let pages = stored(&storage);
+        let imported = import(&export(&pages), &Page::Collection("Inbox".to_string())).unwrap();
+        let mut expected = lines(&pages);
+        expected.insert(1, (Page::Day(day(2024, 6, 1)), vec![". Book the dentist".to_string()]));
+        assert_eq!(lines(&imported), expected);
+
+        // Once the copy is there too, the task is left to it rather than copied again
+        let reimported = import(&export(&imported), &Page::Future).unwrap();
+        expected[0].1.retain(|line| !line.starts_with('<'));
+        assert_eq!(lines(&reimported), expected);
+    }
+
+    #[test]
+    fn other_org_is_read_as_well_as_it_can_be() {
+        tz::set_home(tz::Timezone::from_str("+00:00").unwrap());
+        let text = "#+TITLE: Plans\nLoose text\n* <2024-05-03 Fri>\n:PROPERTIES:\n:ID: 1234\n:END:\n\
+                    ** Meeting                                   :work:urgent:\n\
+                    SCHEDULED: <2024-05-10 Fri>\n- [ ] Prepare slides\n  + [X] Book the room\n\
+                    ** TODO [#B] Follow up\nSCHEDULED: <2024-05-06 Mon>\nDEADLINE: <2024-05-07 Tue>\n";
+        let imported = import(text, &Page::Future).unwrap();
+        assert_eq!(lines(&imported), vec![
+            (Page::Future, vec!["- Loose text".to_string()]),
+            (Page::Day(day(2024, 5, 3)), vec![
+                "o Meeting #work #urgent".to_string(),
+                ". Prepare slides".to_string(),
+                "  x Book the room".to_string(),
+                "*<2024-05-06T12:00+00:00 Follow up".to_string(),
+            ]),
+            (Page::Day(day(2024, 5, 6)), vec!["*. Follow up".to_string()]),
+        ]);
+        // The open copy points back at the task it was scheduled from
+        assert_eq!(imported[2].1[0].origin, Some(imported[1].1[3].id));
+    }
+
+    #[test]
+    fn content_that_looks_like_markup_is_escaped() {
+        tz::set_home(tz::Timezone::from_str("+00:00").unwrap());
+        let storage = MemoryStorage::default();
+        let entries = [
+            "o TODO list review",
+            "o DONE",
+            ". CANCELLED flights need rebooking",
+            "*x [#B] is the cookie syntax",
+            "- [ ] is how Org writes a checkbox",
+            "- \u{200B}already escaped",
+            "- TODOs aren't keywords",
+        ];
+        save(&storage, &Page::Future, &entries);
+        let exported = export(&stored(&storage));
+        assert!(exported.contains("** \u{200B}TODO list review\n"), "{}", exported);
+        assert!(exported.contains("** DONE [#A] \u{200B}[#B] is the cookie syntax\n"), "{}", exported);
+        assert!(exported.contains("- \u{200B}[ ] is how Org writes a checkbox\n"), "{}", exported);
+        assert!(exported.contains("- TODOs aren't keywords\n"), "{}", exported);
+        let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+        assert_eq!(lines(&import(&exported, &Page::Collection("Inbox".to_string())).unwrap()),
+                   vec![(Page::Future, entries)]);
+    }
+
+    #[test]
+    fn headlines_have_to_be_usable_titles() {
+        for text in ["* ../../orgesc\n- Note", "* \n- Note", "* Bad\\name"] {
+            assert!(import(text, &Page::Future).is_err(), "'{}' should have been refused", text);
+        }
+        assert_eq!(import("* Fine\n** MIGRATED Somewhere", &Page::Future).unwrap_err().line, 2);
+        assert_eq!(import("* Fine\n** TODO Soon\nSCHEDULED: someday", &Page::Future).unwrap_err().line, 3);
+    }
+}
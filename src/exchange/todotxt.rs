@@ -0,0 +1,227 @@
+//! todo.txt, so tasks can be worked on with the tools built around it. Each task is a line like
+//! `(A) 2024-05-03 Send the invoices +work @email`:
+//!
+//! - done tasks start with `x` and the day they were done, taken to be the day of their page
+//! - the day of the task's page is its creation date
+//! - the priority signifier is priority `(A)`, or `pri:A` once done, and importing takes any
+//!   priority as it
+//! - `#tags` are written as `+projects`, and `@contexts` are the same in both
+//!
+//! Only open and done tasks are exported, apart from scheduled tasks whose copy isn't exported
+//! too, which are exported as open tasks with a `due:` day. Nesting is flattened. Importing puts
+//! each task on the day it was created, or done when only that is known. Open tasks with a `due:`
+//! day are scheduled for it, with an open copy on that day.
+
+use entry::{Entry, EntryState, Signifier};
+use exchange::{push_entry, ImportError, Pages};
+use page::Page;
+use storage::scheduled_day;
+use tz;
+
+use std::collections::BTreeSet;
+
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+/// Replace `from` with `to` at the start of words that are tags or projects. Only words with a
+/// letter after the marker count, so phone numbers like `+1 555 0100` and `#1` stay as they are.
+fn swap_marker(text: &str, from: char, to: char) -> String {
+    let words: Vec<String> = text.split(' ').map(|word| {
+        match word.strip_prefix(from) {
+            Some(rest) if rest.starts_with(char::is_alphabetic) => format!("{}{}", to, rest),
+            _ => word.to_string()
+        }
+    }).collect();
+    words.join(" ")
+}
+
+fn date(day: NaiveDate) -> String {
+    day.format("%Y-%m-%d").to_string()
+}
+
+/// A task as a todo.txt line, or `None` when the entry isn't exported
+fn task_line(page: &Page, entry: &Entry, copied: &BTreeSet<Uuid>) -> Option<String> {
+    let created = match page {
+        Page::Day(day) => Some(*day),
+        _ => None
+    };
+    let priority = entry.signifiers.contains(&Signifier::Priority);
+    let mut parts = Vec::new();
+    let mut extra = Vec::new();
+    match entry.state {
+        EntryState::Completed => {
+            parts.push("x".to_string());
+            // Nothing records when it was done, and a completion date needs a creation date
+            if let Some(day) = created {
+                parts.push(date(day));
+            }
+            // Done tasks don't have a priority of their own
+            if priority {
+                extra.push("pri:A".to_string());
+            }
+        }
+        EntryState::Incomplete | EntryState::Scheduled(_) if priority => parts.push("(A)".to_string()),
+        EntryState::Incomplete | EntryState::Scheduled(_) => (),
+        _ => return None
+    }
+    if let EntryState::Scheduled(ref when) = entry.state {
+        if copied.contains(&entry.id) {
+            return None;
+        }
+        extra.push(format!("due:{}", date(tz::home().date_of(when))));
+    }
+    if let Some(day) = created {
+        parts.push(date(day));
+    }
+    parts.push(swap_marker(&entry.content, '#', '+'));
+    parts.extend(extra);
+    Some(parts.join(" "))
+}
+
+pub fn export(pages: &[(Page, Vec<Entry>)]) -> String {
+    let copied: BTreeSet<Uuid> = pages.iter()
+        .flat_map(|(_, entries)| entries.iter().filter_map(|entry| entry.origin))
+        .collect();
+    let mut out = String::new();
+    for (page, entries) in pages {
+        for line in entries.iter().filter_map(|entry| task_line(page, entry, &copied)) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Take a leading `YYYY-MM-DD` off the words
+fn take_date(words: &mut &[&str]) -> Option<NaiveDate> {
+    let day = NaiveDate::parse_from_str(words.first()?, "%Y-%m-%d").ok()?;
+    *words = &words[1..];
+    Some(day)
+}
+
+/// Parse a todo.txt line into a task, along with the day it belongs on if it says
+fn parse_task(line: &str) -> Result<(Entry, Option<NaiveDate>), String> {
+    let all: Vec<&str> = line.split(' ').collect();
+    let mut words = &all[..];
+    let done = words.first() == Some(&"x");
+    if done {
+        words = &words[1..];
+    }
+    let priority = words.first().is_some_and(|word| {
+        let bytes = word.as_bytes();
+        bytes.len() == 3 && bytes[0] == b'(' && bytes[1].is_ascii_uppercase() && bytes[2] == b')'
+    });
+    if priority {
+        words = &words[1..];
+    }
+    let first = take_date(&mut words);
+    // Done tasks have their completion date first
+    let second = if done && first.is_some() { take_date(&mut words) } else { None };
+    let day = second.or(first);
+    // Done tasks keep their priority as an extension
+    let kept_priority = done && words.iter().any(|word| word.starts_with("pri:"));
+    let due = words.iter().find_map(|word| NaiveDate::parse_from_str(word.strip_prefix("due:")?, "%Y-%m-%d").ok());
+    let words: Vec<&str> = words.iter().cloned()
+        .filter(|word| !(done && word.starts_with("pri:") || due.is_some() && word.starts_with("due:")))
+        .collect();
+    let content = swap_marker(&words.join(" "), '+', '#');
+    if content.trim().is_empty() {
+        return Err("The task has no text".to_string());
+    }
+    let state = match due {
+        _ if done => EntryState::Completed,
+        Some(due) => EntryState::Scheduled(tz::anchor(due)),
+        None => EntryState::Incomplete
+    };
+    let mut entry = Entry::new(&content, state);
+    if priority || kept_priority {
+        entry.signifiers.insert(Signifier::Priority);
+    }
+    Ok((entry, day))
+}
+
+/// The tasks of a todo.txt file, on the days they were created. Tasks without a date go on
+/// `default`.
+pub fn import(text: &str, default: &Page) -> Result<Pages, ImportError> {
+    let mut pages = Pages::new();
+    for (idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (entry, day) = parse_task(line.trim()).map_err(|message| ImportError {line: idx + 1, message})?;
+        let page = day.map_or_else(|| default.clone(), Page::Day);
+        // Like scheduling with <, an open copy of the task goes on the day it's due
+        let copy = scheduled_day(&entry).map(|due| (Page::Day(due), entry.copy(EntryState::Incomplete)));
+        push_entry(&mut pages, &page, entry);
+        if let Some((due, copy)) = copy {
+            push_entry(&mut pages, &due, copy);
+        }
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exchange::tests::{day, lines, save, stored};
+    use storage::MemoryStorage;
+
+    #[test]
+    fn exported_tasks_import_the_same() {
+        tz::set_home(tz::Timezone::from_str("+00:00").unwrap());
+        let storage = MemoryStorage::default();
+        let tasks = ["*. Send the invoices #work @email", "*x Filed the taxes", "<2024-06-01T12:00+00:00 Book the dentist"];
+        save(&storage, &Page::Day(day(2024, 5, 3)), &[tasks[0], "- Notes aren't exported", tasks[1], tasks[2], "/ Nor cancelled tasks"]);
+        let exported = export(&stored(&storage));
+        assert_eq!(exported, "(A) 2024-05-03 Send the invoices +work @email\nx 2024-05-03 2024-05-03 Filed the taxes pri:A\n\
+                              2024-05-03 Book the dentist due:2024-06-01\n");
+        let tasks: Vec<String> = tasks.iter().map(|task| task.to_string()).collect();
+        assert_eq!(lines(&import(&exported, &Page::Future).unwrap()), vec![
+            (Page::Day(day(2024, 5, 3)), tasks),
+            (Page::Day(day(2024, 6, 1)), vec![". Book the dentist".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn tasks_go_on_the_day_they_were_made() {
+        tz::set_home(tz::Timezone::from_str("+00:00").unwrap());
+        let text = "(B) Call mum +family\nx 2024-05-04 2024-05-01 Pay rent due:2024-05-05\n\n2024-05-02 Renew passport due:2024-07-01 @town\n";
+        let imported = import(text, &Page::Future).unwrap();
+        assert_eq!(lines(&imported), vec![
+            (Page::Future, vec!["*. Call mum #family".to_string()]),
+            (Page::Day(day(2024, 5, 1)), vec!["x Pay rent".to_string()]),
+            (Page::Day(day(2024, 5, 2)), vec!["<2024-07-01T12:00+00:00 Renew passport @town".to_string()]),
+            (Page::Day(day(2024, 7, 1)), vec![". Renew passport @town".to_string()]),
+        ]);
+        // The open copy points back at the task it was scheduled from
+        assert_eq!(imported[3].1[0].origin, Some(imported[2].1[0].id));
+        assert!(imported[3].1[0].state.is_open());
+    }
+
+    #[test]
+    fn only_the_start_of_a_line_is_special() {
+        tz::set_home(tz::Timezone::from_str("+00:00").unwrap());
+        let text = "xylophone lessons\n(a) lowercase isn't a priority\nCall +1 555 0100 about the #1 spot +home\n\
+                    Fix the x axis from 2024-05-03 due:someday pri:B\nx Done without a date\n";
+        assert_eq!(lines(&import(text, &Page::Future).unwrap()), vec![
+            (Page::Future, vec![
+                ". xylophone lessons".to_string(),
+                ". (a) lowercase isn't a priority".to_string(),
+                ". Call +1 555 0100 about the #1 spot #home".to_string(),
+                ". Fix the x axis from 2024-05-03 due:someday pri:B".to_string(),
+                "x Done without a date".to_string(),
+            ]),
+        ]);
+        // Numbers aren't projects on the way out either
+        let storage = MemoryStorage::default();
+        save(&storage, &Page::Future, &[". Call +1 555 0100 about the #1 spot #home"]);
+        assert_eq!(export(&stored(&storage)), "Call +1 555 0100 about the #1 spot +home\n");
+    }
+
+    #[test]
+    fn empty_tasks_are_errors() {
+        for text in ["Fine\nx 2024-05-03 ", "Fine\n(A) 2024-05-03", "Fine\nx due:2024-05-03"] {
+            assert_eq!(import(text, &Page::Future).unwrap_err().line, 2, "'{}' should have been refused", text);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Undo and redo. Every change to a page's entries is saved through here, which remembers what
+//! the page held before and after so the change can be reverted and reapplied.
+
+use entry::Entry;
+use page::Page;
+use storage::{self, StorageError};
+
+use std::cell::RefCell;
+
+use uuid::Uuid;
+
+/// How many steps are kept before the oldest are forgotten
+const LIMIT: usize = 100;
+
+/// A single page being saved
+struct Edit {
+    page: Page,
+    /// `None` when the page didn't exist before, so undoing removes it again
+    before: Option<Vec<Entry>>,
+    /// `None` when the page was deleted, so redoing removes it again
+    after: Option<Vec<Entry>>,
+}
+
+impl Edit {
+    /// The first position the edit changed, to select once it's been undone or redone
+    fn changed_at(&self) -> usize {
+        let before = self.before.as_ref().map_or(&[][..], |entries| &entries[..]);
+        let after = self.after.as_ref().map_or(&[][..], |entries| &entries[..]);
+        (0..).find(|&idx| before.get(idx).map(Entry::to_string) != after.get(idx).map(Entry::to_string))
+            .unwrap_or(0)
+    }
+}
+
+/// Everything saved by one user action, which is undone or redone as a whole
+type Step = Vec<Edit>;
+
+#[derive(Default)]
+struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    /// The step being recorded by `batch`
+    open: Option<Step>,
+}
+
+thread_local! {
+    static HISTORY: RefCell<History> = RefCell::new(History::default());
+}
+
+/// Save the entries of a page, remembering what it held so the change can be undone
+pub fn save(page: &Page, entries: &[Entry]) -> Result<(), StorageError> {
+    let storage = storage::current();
+    let before = storage.load(page)?;
+    let before = if before.is_empty() && !storage.exists(page)? { None } else { Some(before) };
+    storage.save(page, entries)?;
+    if before.as_ref().is_some_and(|before| same_lines(before, entries)) {
+        // Nothing changed, so there's nothing to undo
+        return Ok(());
+    }
+    record(Edit {page: page.clone(), before, after: Some(entries.to_vec())});
+    Ok(())
+}
+
+/// Remove a page and all of its entries, remembering them so the page can be brought back.
+/// Deleting a page that doesn't exist is not an error, and there's nothing to undo.
+pub fn delete(page: &Page) -> Result<(), StorageError> {
+    let storage = storage::current();
+    if !storage.exists(page)? {
+        return Ok(());
+    }
+    let before = storage.load(page)?;
+    storage.delete(page)?;
+    record(Edit {page: page.clone(), before: Some(before), after: None});
+    Ok(())
+}
+
+fn record(edit: Edit) {
+    HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        history.redo.clear();
+        match history.open {
+            Some(ref mut step) => step.push(edit),
+            None => push(&mut history.undo, vec![edit])
+        }
+    });
+}
+
+/// Change a single entry on a page without touching the rest, returning the changed entry.
+/// `None` when the page doesn't have the entry (anymore).
+pub fn update_entry<F: FnOnce(&mut Entry)>(page: &Page, id: Uuid, change: F) -> Result<Option<Entry>, StorageError> {
+    let mut entries = storage::current().load(page)?;
+    let updated = match entries.iter_mut().find(|entry| entry.id == id) {
+        Some(entry) => {
+            change(entry);
+            entry.clone()
+        }
+        None => return Ok(None)
+    };
+    save(page, &entries)?;
+    Ok(Some(updated))
+}
+
+/// Run `f`, undoing everything it saves in one step. Used for actions changing several pages.
+pub fn batch<R, F: FnOnce() -> R>(f: F) -> R {
+    let nested = HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let nested = history.open.is_some();
+        if !nested {
+            history.open = Some(Vec::new());
+        }
+        nested
+    });
+    let result = f();
+    if !nested {
+        HISTORY.with(|history| {
+            let mut history = history.borrow_mut();
+            match history.open.take() {
+                Some(ref step) if step.is_empty() => (),
+                Some(step) => push(&mut history.undo, step),
+                None => ()
+            }
+        });
+    }
+    result
+}
+
+fn same_lines(a: &[Entry], b: &[Entry]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_string() == b.to_string())
+}
+
+fn push(steps: &mut Vec<Step>, step: Step) {
+    steps.push(step);
+    if steps.len() > LIMIT {
+        steps.remove(0);
+    }
+}
+
+/// The page to show once a step has been undone or redone and the position to select there: the
+/// first page the step left in place, or the first page it touched when it removed them all
+fn to_show<F: Fn(&Edit) -> bool>(step: &[Edit], kept: F) -> Option<(Page, usize)> {
+    step.iter().find(|edit| kept(edit)).or_else(|| step.first())
+        .map(|edit| (edit.page.clone(), edit.changed_at()))
+}
+
+/// Revert the last change, returning the page it started on and the position it changed there.
+/// `None` when there's nothing to undo.
+pub fn undo() -> Result<Option<(Page, usize)>, StorageError> {
+    let step = match HISTORY.with(|history| history.borrow_mut().undo.pop()) {
+        Some(step) => step,
+        None => return Ok(None)
+    };
+    let storage = storage::current();
+    for edit in step.iter().rev() {
+        match edit.before {
+            Some(ref entries) => storage.save(&edit.page, entries)?,
+            None => storage.delete(&edit.page)?
+        }
+    }
+    let shown = to_show(&step, |edit| edit.before.is_some());
+    HISTORY.with(|history| push(&mut history.borrow_mut().redo, step));
+    Ok(shown)
+}
+
+/// Reapply the last undone change, returning the page it started on and the position it changed
+/// there. `None` when there's nothing to redo.
+pub fn redo() -> Result<Option<(Page, usize)>, StorageError> {
+    let step = match HISTORY.with(|history| history.borrow_mut().redo.pop()) {
+        Some(step) => step,
+        None => return Ok(None)
+    };
+    let storage = storage::current();
+    for edit in step.iter() {
+        match edit.after {
+            Some(ref entries) => storage.save(&edit.page, entries)?,
+            None => storage.delete(&edit.page)?
+        }
+    }
+    let shown = to_show(&step, |edit| edit.after.is_some());
+    HISTORY.with(|history| push(&mut history.borrow_mut().undo, step));
+    Ok(shown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entry::EntryState;
+    use storage::MemoryStorage;
+    use tz;
+
+    use std::rc::Rc;
+
+    use chrono::NaiveDate;
+
+    fn contents(page: &Page) -> Vec<String> {
+        storage::current().load(page).unwrap().into_iter().map(|entry| entry.content).collect()
+    }
+
+    fn day(day: u32) -> Page {
+        Page::Day(NaiveDate::from_ymd_opt(2024, 5, day).unwrap())
+    }
+
+    #[test]
+    fn batches_are_undone_and_redone_together() {
+        storage::install(Rc::new(MemoryStorage::default()));
+        let entries = vec![Entry::new("Pay rent", EntryState::Incomplete)];
+        let task = &entries[0];
+        save(&day(1), &entries).unwrap();
+        batch(|| {
+            let migrated = EntryState::Collected(tz::anchor(NaiveDate::from_ymd_opt(2024, 5, 2).unwrap()));
+            update_entry(&day(1), task.id, |entry| entry.state = migrated).unwrap();
+            save(&day(2), &[task.copy(EntryState::Incomplete)]).unwrap();
+        });
+
+        assert_eq!(undo().unwrap(), Some((day(1), 0)));
+        assert!(storage::current().load(&day(1)).unwrap()[0].state.is_open());
+        // The page didn't exist before the batch, so it's gone again
+        assert!(!storage::current().list().unwrap().contains(&day(2)));
+
+        assert_eq!(redo().unwrap(), Some((day(1), 0)));
+        assert_eq!(contents(&day(2)), vec!["Pay rent"]);
+
+        assert_eq!(undo().unwrap(), Some((day(1), 0)));
+        assert_eq!(undo().unwrap(), Some((day(1), 0)));
+        assert!(storage::current().list().unwrap().is_empty());
+        assert_eq!(undo().unwrap(), None);
+    }
+
+    #[test]
+    fn deleted_pages_come_back() {
+        storage::install(Rc::new(MemoryStorage::default()));
+        let old = Page::Collection("Books".to_string());
+        let new = Page::Collection("Reading list".to_string());
+        save(&old, &[Entry::new("Dune", EntryState::Note)]).unwrap();
+        save(&new, &[]).unwrap();
+        // Renaming onto an empty collection, as one step
+        batch(|| {
+            save(&new, &storage::current().load(&old).unwrap()).unwrap();
+            delete(&old).unwrap();
+        });
+        assert_eq!(storage::current().list().unwrap(), vec![new.clone()]);
+
+        assert_eq!(undo().unwrap(), Some((new.clone(), 0)));
+        assert_eq!(contents(&old), vec!["Dune"]);
+        assert!(contents(&new).is_empty());
+        assert!(storage::current().exists(&new).unwrap());
+        // Only the renamed collection is left in place
+        assert_eq!(redo().unwrap(), Some((new.clone(), 0)));
+        assert!(!storage::current().exists(&old).unwrap());
+
+        delete(&new).unwrap();
+        assert!(storage::current().list().unwrap().is_empty());
+        assert_eq!(undo().unwrap(), Some((new.clone(), 0)));
+        assert_eq!(contents(&new), vec!["Dune"]);
+        // Deleting what isn't there isn't a change, so the deletion can still be redone
+        delete(&old).unwrap();
+        assert_eq!(redo().unwrap(), Some((new.clone(), 0)));
+        assert!(!storage::current().exists(&new).unwrap());
+    }
+
+    #[test]
+    fn nested_batches_are_one_step() {
+        storage::install(Rc::new(MemoryStorage::default()));
+        batch(|| {
+            save(&day(1), &[Entry::new("one", EntryState::Note)]).unwrap();
+            batch(|| save(&day(2), &[Entry::new("two", EntryState::Note)]).unwrap());
+        });
+        undo().unwrap();
+        assert!(storage::current().list().unwrap().is_empty());
+        assert_eq!(undo().unwrap(), None);
+    }
+
+    #[test]
+    fn saving_again_forgets_what_was_undone() {
+        storage::install(Rc::new(MemoryStorage::default()));
+        let entries = vec![Entry::new("first", EntryState::Note)];
+        save(&day(1), &entries).unwrap();
+        // Saving what's already there isn't a change
+        save(&day(1), &entries).unwrap();
+        batch(|| ());
+        undo().unwrap();
+        assert_eq!(undo().unwrap(), None);
+
+        redo().unwrap();
+        save(&day(1), &[Entry::new("second", EntryState::Note)]).unwrap();
+        assert_eq!(redo().unwrap(), None);
+        undo().unwrap();
+        assert_eq!(contents(&day(1)), vec!["first"]);
+    }
+}
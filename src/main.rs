@@ -1,25 +1,62 @@
 extern crate cursive;
 extern crate chrono;
+extern crate chrono_tz;
+extern crate fst;
+extern crate serde;
+extern crate serde_json;
+extern crate uuid;
 extern crate xdg;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
 
 
 // my modules
+mod cli;
 mod entry;
+mod exchange;
+mod history;
+mod page;
+mod query;
+mod search;
+mod storage;
+mod tz;
 mod views;
 use views::daily::daily_view;
+use views::upgrade::upgrade_view;
 
 // external modules
-use chrono::Local;
 use cursive::Cursive;
 use cursive::event::Event;
 use cursive::views::{Dialog, TextView};
 
+use std::env;
+use std::process;
+
 
 fn main() {
+    match tz::home_from_env() {
+        Ok(zone) => tz::set_home(zone),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    match storage::from_env() {
+        Ok(backend) => storage::install(backend),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        process::exit(cli::run(&args));
+    }
     // Creates the cursive root - required for every application.
     let mut siv = Cursive::default();
+    views::install_reports(&siv);
     // Load today's data
-    let today = Local::today();
+    let today = tz::home().today();
     // TODO: move this into the DailyView
     siv.add_global_callback(Event::Char('q'), |s| {
             s.screen_mut().add_layer(
@@ -27,7 +64,14 @@ fn main() {
                     .dismiss_button("No")
                     .button("Yes", |s2| s2.quit()));
     });
-    siv.add_layer(daily_view(today.naive_local()));
+    match storage::current().pending_upgrade() {
+        Ok(ref changes) if !changes.is_empty() => siv.add_layer(upgrade_view(changes, today)),
+        Ok(_) => siv.add_layer(daily_view(today)),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
 
     // Starts the event loop.
     siv.run();
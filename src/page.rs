@@ -0,0 +1,136 @@
+use entry::{Entry, EntryState};
+
+use chrono::{Datelike, NaiveDate};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::fmt;
+
+/// A page of the journal: a single list of entries that is saved and loaded as one unit.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Page {
+    /// The daily log for the given day
+    Day(NaiveDate),
+    /// The task list of the monthly log. Always keyed by the first day of the month
+    Month(NaiveDate),
+    /// Free-floating entries of the future log that don't belong to a particular day yet
+    Future,
+    /// A named collection of entries that isn't tied to any date
+    Collection(String),
+}
+
+impl Page {
+    /// Build the monthly page containing the given date
+    pub fn month_of(date: NaiveDate) -> Page {
+        Page::Month(first_of_month(date))
+    }
+
+    /// A short unique name for the page, for storing references to it
+    pub fn key(&self) -> String {
+        match self {
+            Page::Day(date) => format!("day:{}", date.format("%Y-%m-%d")),
+            Page::Month(date) => format!("month:{}", date.format("%Y-%m-%d")),
+            Page::Future => "future".to_string(),
+            Page::Collection(name) => format!("collection:{}", name),
+        }
+    }
+
+    /// The page named by `key`
+    pub fn from_key(key: &str) -> Option<Page> {
+        let date = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+        match key.find(':').map(|idx| key.split_at(idx)) {
+            Some(("day", date_str)) => date(&date_str[1..]).map(Page::Day),
+            Some(("month", date_str)) => date(&date_str[1..]).map(Page::Month),
+            Some(("collection", name)) => Some(Page::Collection(name[1..].to_string())),
+            None if key == "future" => Some(Page::Future),
+            _ => None
+        }
+    }
+
+    /// The page with the title `Display` gives it. Titles that aren't a day, a month or the future
+    /// log are taken to be collections, as long as they can be used as a collection name.
+    pub fn from_title(title: &str) -> Result<Page, String> {
+        let title = title.trim();
+        if let Ok(date) = NaiveDate::parse_from_str(title, "%Y-%m-%d") {
+            Ok(Page::Day(date))
+        } else if let Ok(date) = NaiveDate::parse_from_str(&format!("1 {}", title), "%d %B %Y") {
+            Ok(Page::Month(date))
+        } else if title == "Future Log" {
+            Ok(Page::Future)
+        } else if valid_collection_name(title) {
+            Ok(Page::Collection(title.to_string()))
+        } else if title.is_empty() {
+            Err("Pages need a title".to_string())
+        } else {
+            Err(format!("'{}' can't be used as a collection name", title))
+        }
+    }
+}
+
+impl fmt::Display for Page {
+    /// The human-readable title of the page
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Page::Day(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            Page::Month(date) => write!(f, "{}", date.format("%B %Y")),
+            Page::Future => write!(f, "Future Log"),
+            Page::Collection(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Serialised as the `kind` of page, with the `date` of daily and monthly pages or the `name` of
+/// a collection
+impl Serialize for Page {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            Page::Day(date) => {
+                map.serialize_entry("kind", "day")?;
+                map.serialize_entry("date", date)?;
+            }
+            Page::Month(date) => {
+                map.serialize_entry("kind", "month")?;
+                map.serialize_entry("date", date)?;
+            }
+            Page::Future => map.serialize_entry("kind", "future")?,
+            Page::Collection(name) => {
+                map.serialize_entry("kind", "collection")?;
+                map.serialize_entry("name", name)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// The first day of the month containing `date`
+pub fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).expect("Every month has a first day")
+}
+
+/// The first day of the month `months` months away from the one containing `date`.
+/// Negative values go back in time.
+pub fn add_months(date: NaiveDate, months: i32) -> NaiveDate {
+    let index = date.year() * 12 + date.month0() as i32 + months;
+    NaiveDate::from_ymd_opt(index.div_euclid(12), index.rem_euclid(12) as u32 + 1, 1)
+        .expect("The first of a month is always a valid date")
+}
+
+/// Every day in the month containing `date`, in order
+pub fn days_of_month(date: NaiveDate) -> Vec<NaiveDate> {
+    let first = first_of_month(date);
+    first.iter_days().take_while(|day| day.month() == first.month()).collect()
+}
+
+/// Collection names become file names, so they can't be empty or contain path separators
+pub fn valid_collection_name(name: &str) -> bool {
+    let name = name.trim();
+    !name.is_empty() && !name.starts_with('.') && !name.contains('/') && !name.contains('\\')
+}
+
+/// A single calendar line: the day of the month, the weekday and every event of that day
+pub fn calendar_line(day: NaiveDate, month_entries: &[(NaiveDate, Entry)]) -> String {
+    let events: Vec<String> = month_entries.iter()
+        .filter(|(entry_day, entry)| *entry_day == day && matches!(entry.state, EntryState::Event))
+        .map(|(_, entry)| entry.to_display())
+        .collect();
+    format!("{} {}", day.format("%d %a"), events.join(", "))
+}
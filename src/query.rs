@@ -0,0 +1,266 @@
+//! Structured queries like `state:open tag:work after:2024-01-01 before:2024-02-01 "budget"`.
+//! Every term of a query has to match, and any term can be negated with a leading `-`.
+
+use entry::{words_marked, Entry, EntryState, Signifier};
+use page::{self, Page};
+use storage::{self, Storage, StorageError, Unreadable};
+use tz;
+
+use std::fmt;
+
+use chrono::NaiveDate;
+
+#[derive(Debug)]
+pub struct QueryError(String);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The states `state:` can ask for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StateFilter {
+    Open,
+    Done,
+    Cancelled,
+    /// Any task, whether it's open or closed
+    Task,
+    Note,
+    Event,
+    Scheduled,
+    Migrated,
+}
+
+impl StateFilter {
+    fn from_str(name: &str) -> Option<StateFilter> {
+        match name {
+            "open" => Some(StateFilter::Open),
+            "done" => Some(StateFilter::Done),
+            "cancelled" => Some(StateFilter::Cancelled),
+            "task" => Some(StateFilter::Task),
+            "note" => Some(StateFilter::Note),
+            "event" => Some(StateFilter::Event),
+            "scheduled" => Some(StateFilter::Scheduled),
+            "migrated" => Some(StateFilter::Migrated),
+            _ => None
+        }
+    }
+
+    fn matches(self, state: &EntryState) -> bool {
+        matches!((self, state),
+                 (StateFilter::Open, EntryState::Incomplete)
+                 | (StateFilter::Done, EntryState::Completed)
+                 | (StateFilter::Cancelled, EntryState::Cancelled)
+                 | (StateFilter::Task, EntryState::Incomplete)
+                 | (StateFilter::Task, EntryState::Completed)
+                 | (StateFilter::Task, EntryState::Cancelled)
+                 | (StateFilter::Note, EntryState::Note)
+                 | (StateFilter::Event, EntryState::Event)
+                 | (StateFilter::Scheduled, EntryState::Scheduled(_))
+                 | (StateFilter::Migrated, EntryState::Collected(_)))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    State(StateFilter),
+    Tag(String),
+    Context(String),
+    Signifier(Signifier),
+    /// Pages dated on or after the day
+    After(NaiveDate),
+    /// Pages dated before the day
+    Before(NaiveDate),
+    /// Text the entry contains, ignoring case
+    Text(String),
+}
+
+impl Term {
+    fn matches(&self, page: &Page, entry: &Entry) -> bool {
+        match self {
+            Term::State(filter) => filter.matches(&entry.state),
+            Term::Tag(tag) => entry.tags.contains(tag),
+            Term::Context(context) => entry.contexts.contains(context),
+            Term::Signifier(signifier) => entry.signifiers.contains(signifier),
+            Term::After(day) => page_date(page).is_some_and(|date| date >= *day),
+            Term::Before(day) => page_date(page).is_some_and(|date| date < *day),
+            Term::Text(text) => entry.content.to_lowercase().contains(text.as_str()),
+        }
+    }
+}
+
+/// The day a page is dated. Monthly logs count as the first of their month, and pages that
+/// aren't tied to a date never match `after:` or `before:`.
+fn page_date(page: &Page) -> Option<NaiveDate> {
+    match page {
+        Page::Day(date) => Some(*date),
+        Page::Month(date) => Some(page::first_of_month(*date)),
+        _ => None
+    }
+}
+
+/// The lowercased word after a `#` or `@`, as it's kept on entries
+fn marked_word(word: &str, marker: char) -> Result<String, QueryError> {
+    let marked = if word.starts_with(marker) { word.to_string() } else { format!("{}{}", marker, word) };
+    words_marked(&marked, marker).pop()
+        .ok_or_else(|| QueryError(format!("Expected a word after '{}'", marker)))
+}
+
+/// Parse a single unquoted word of a query
+fn parse_term(word: &str) -> Result<Term, QueryError> {
+    if word.starts_with('#') {
+        return Ok(Term::Tag(marked_word(word, '#')?));
+    }
+    if word.starts_with('@') {
+        return Ok(Term::Context(marked_word(word, '@')?));
+    }
+    let (field, value) = match word.find(':').map(|idx| word.split_at(idx)) {
+        Some((field, value)) if !field.is_empty() && field.chars().all(|c| c.is_ascii_alphabetic()) => (field, &value[1..]),
+        _ => return Ok(Term::Text(word.to_lowercase()))
+    };
+    match field {
+        "state" | "is" => StateFilter::from_str(value)
+            .map(Term::State)
+            .ok_or_else(|| QueryError(format!("Unknown state '{}'. Try open, done, cancelled, task, note, event, scheduled or migrated", value))),
+        "tag" => Ok(Term::Tag(marked_word(value, '#')?)),
+        "context" => Ok(Term::Context(marked_word(value, '@')?)),
+        "sig" => Signifier::ALL.iter().cloned()
+            .find(|signifier| signifier.name() == value || value.chars().eq(Some(signifier.symbol())))
+            .map(Term::Signifier)
+            .ok_or_else(|| QueryError(format!("Unknown signifier '{}'. Try priority, inspiration or explore", value))),
+        "after" => Ok(Term::After(tz::parse_day(value).map_err(QueryError)?)),
+        "before" => Ok(Term::Before(tz::parse_day(value).map_err(QueryError)?)),
+        _ => Err(QueryError(format!("Unknown query field '{}'. Try state, tag, context, sig, after or before", field)))
+    }
+}
+
+/// A parsed query. Each term is kept with whether it was negated.
+#[derive(Debug, Clone)]
+pub struct Query {
+    terms: Vec<(bool, Term)>,
+}
+
+impl Query {
+    /// Parse a query. Words are separated by spaces, and double quotes keep a phrase together as
+    /// text to look for.
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let mut terms = Vec::new();
+        let mut chars = text.chars().peekable();
+        loop {
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            let negated = chars.peek() == Some(&'-');
+            if negated {
+                chars.next();
+                if chars.peek().is_none_or(|c| c.is_whitespace()) {
+                    return Err(QueryError("Expected something to leave out after '-'".to_string()));
+                }
+            }
+            match chars.peek() {
+                None => break,
+                Some('"') => {
+                    chars.next();
+                    let phrase: String = chars.by_ref().take_while(|c| *c != '"').collect();
+                    terms.push((negated, Term::Text(phrase.to_lowercase())));
+                }
+                Some(_) => {
+                    let mut word = String::new();
+                    while let Some(c) = chars.peek().cloned().filter(|c| !c.is_whitespace()) {
+                        word.push(c);
+                        chars.next();
+                    }
+                    terms.push((negated, parse_term(&word)?));
+                }
+            }
+        }
+        Ok(Query {terms})
+    }
+
+    /// Whether an entry on the page matches every term
+    pub fn matches(&self, page: &Page, entry: &Entry) -> bool {
+        self.terms.iter().all(|(negated, term)| term.matches(page, entry) != *negated)
+    }
+
+    /// Every entry in the storage matching the query, sorted by page then by position. Pages that
+    /// can't be read are skipped and returned alongside.
+    pub fn run(&self, storage: &dyn Storage) -> Result<(Vec<(Page, Entry)>, Unreadable), StorageError> {
+        let (entries, unreadable) = match storage.entries() {
+            Ok(entries) => (entries, Vec::new()),
+            // Go through the pages one by one, so a page that can't be read doesn't hide the rest
+            Err(_) => storage::readable_entries(storage, |_| true)?
+        };
+        Ok((entries.into_iter().filter(|(page, entry)| self.matches(page, entry)).collect(), unreadable))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::FileStorage;
+    use storage::tests::TempDir;
+
+    use std::fs;
+
+    fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn matches(query: &str, page: &Page, entry: &Entry) -> bool {
+        Query::parse(query).unwrap().matches(page, entry)
+    }
+
+    #[test]
+    fn every_term_has_to_match() {
+        let page = Page::Day(day(2024, 1, 15));
+        let mut entry = Entry::new("Draft the Budget for #work @office", EntryState::Incomplete);
+        entry.signifiers.insert(Signifier::Priority);
+        assert!(matches("state:open tag:work context:office sig:priority budget", &page, &entry));
+        assert!(matches("is:task #Work @office sig:* after:2024-01-01 before:2024-02-01", &page, &entry));
+        assert!(matches("\"the budget\"", &page, &entry));
+        assert!(!matches("state:open tag:home", &page, &entry));
+        assert!(!matches("before:2024-01-15", &page, &entry));
+        assert!(matches("", &page, &entry));
+    }
+
+    #[test]
+    fn terms_can_be_negated() {
+        let page = Page::Collection("Ideas".to_string());
+        let entry = Entry::new("Learn the cello", EntryState::Note);
+        assert!(matches("-state:task -\"the piano\"", &page, &entry));
+        assert!(!matches("-cello", &page, &entry));
+        // Undated pages are never after or before anything
+        assert!(!matches("after:2000-01-01", &page, &entry));
+        assert!(matches("-after:2000-01-01", &page, &entry));
+    }
+
+    #[test]
+    fn months_count_as_their_first_day() {
+        let page = Page::month_of(day(2024, 3, 20));
+        let entry = Entry::new("Taxes", EntryState::Incomplete);
+        assert!(matches("after:2024-03-01 before:2024-03-02", &page, &entry));
+    }
+
+    #[test]
+    fn bad_queries_are_errors() {
+        for query in ["state:later", "sig:nope", "colour:red", "after:someday", "budget -", "tag:"] {
+            assert!(Query::parse(query).is_err(), "'{}' should have been refused", query);
+        }
+    }
+
+    #[test]
+    fn pages_that_cant_be_read_are_skipped() {
+        let dir = TempDir::new();
+        let storage = FileStorage::new(&dir.0);
+        storage.save(&Page::Day(day(2024, 5, 3)), &[Entry::new("Draft the budget", EntryState::Incomplete)]).unwrap();
+        storage.save(&Page::Day(day(2024, 5, 4)), &[Entry::new("Send the budget", EntryState::Incomplete)]).unwrap();
+        fs::write(dir.0.join("2024/05/04.txt"), "garbage\n").unwrap();
+        let (found, unreadable) = Query::parse("budget").unwrap().run(&storage).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.content, "Draft the budget");
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0].0, Page::Day(day(2024, 5, 4)));
+    }
+}
@@ -0,0 +1,314 @@
+//! Global search. Entries are split into lowercased words, and an fst maps every word to the
+//! entries using it, so a search only has to read the pages that can match.
+
+use entry::Entry;
+use page::Page;
+use storage::StorageError;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use fst::automaton::{Automaton, Str};
+use uuid::Uuid;
+
+/// The lowercased words of some text. Anything that isn't a letter or a digit separates words.
+pub fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// How well an entry matches the words of a query, higher being better. Every query word has to
+/// match a word of the entry, either entirely or as its start.
+fn score(query: &[String], entry: &Entry) -> Option<usize> {
+    let entry_words = words(&entry.content);
+    let mut total = 0;
+    for query_word in query {
+        let best = entry_words.iter()
+            .map(|word| if word == query_word { 2 } else if word.starts_with(query_word.as_str()) { 1 } else { 0 })
+            .max()
+            .unwrap_or(0);
+        if best == 0 {
+            return None;
+        }
+        total += best;
+    }
+    Some(total)
+}
+
+/// The day a page belongs to, which breaks ties between equally good matches
+fn page_date(page: &Page) -> Option<NaiveDate> {
+    match page {
+        Page::Day(date) | Page::Month(date) => Some(*date),
+        _ => None
+    }
+}
+
+/// Keep the entries matching every query word, best matches first. Equally good matches on more
+/// recent pages come first.
+pub fn rank(query: &[String], found: Vec<(Page, Entry)>) -> Vec<(Page, Entry)> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut scored: Vec<(usize, Page, Entry)> = found.into_iter()
+        .filter_map(|(page, entry)| score(query, &entry).map(|score| (score, page, entry)))
+        .collect();
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| page_date(&b.1).cmp(&page_date(&a.1))));
+    scored.into_iter().map(|(_, page, entry)| (page, entry)).collect()
+}
+
+/// What the index knows about a page
+struct IndexedPage {
+    /// The storage's modification stamp when the page was indexed, if it keeps them
+    modified: Option<u64>,
+    /// The id and words of every entry
+    entries: Vec<(Uuid, Vec<String>)>,
+}
+
+/// An index from words to the entries using them. It's kept in a directory as `pages`, listing
+/// the words of every indexed entry, and `words.fst`, mapping each word to its place in the
+/// sorted word list. Changed pages are appended to `pages` as they're saved, and the word map is
+/// only rebuilt when it's searched.
+pub struct SearchIndex {
+    /// Indexes without a directory only last as long as the process
+    dir: Option<PathBuf>,
+    pages: BTreeMap<Page, IndexedPage>,
+    words: Map<Vec<u8>>,
+    /// The entries using each word, in the same order as `words`
+    postings: Vec<BTreeSet<(Page, Uuid)>>,
+    /// Pages changed since the index was last built
+    dirty: bool,
+    /// Pages changed since they were last written out
+    unsaved: BTreeSet<Page>,
+}
+
+impl SearchIndex {
+    /// An empty index that isn't saved anywhere
+    pub fn in_memory() -> SearchIndex {
+        SearchIndex {dir: None,
+                     pages: BTreeMap::new(),
+                     words: Map::default(),
+                     postings: Vec::new(),
+                     dirty: false,
+                     unsaved: BTreeSet::new()}
+    }
+
+    /// Open the index kept in `dir`, starting an empty one if there's none yet. An index that
+    /// can't be read is thrown away, leaving every page to be indexed again.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> Result<SearchIndex, StorageError> {
+        let dir = dir.into();
+        let mut index = SearchIndex {dir: Some(dir.clone()), ..SearchIndex::in_memory()};
+        match fs::File::open(dir.join("pages")).map_err(StorageError::from).and_then(|f| read_pages(BufReader::new(f))) {
+            Ok(pages) => index.pages = pages,
+            Err(StorageError::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => return Ok(index),
+            // It's only a cache, so it's rebuilt from the pages themselves
+            Err(_) => {
+                index.dirty = true;
+                return Ok(index);
+            }
+        }
+        index.postings = index.word_postings().into_values().collect();
+        match fs::read(dir.join("words.fst")).ok().and_then(|bytes| Map::new(bytes).ok()) {
+            Some(words) if words.len() == index.postings.len() => index.words = words,
+            // Missing or out of step with the pages, so build it again
+            _ => index.dirty = true
+        }
+        Ok(index)
+    }
+
+    /// The index of the `backend` storage in `$XDG_CACHE_HOME/bullet-terminal/search/<backend>`
+    pub fn xdg(backend: &str) -> Result<SearchIndex, StorageError> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix("bullet-terminal")
+            .map_err(|e| StorageError::Location(e.to_string()))?;
+        SearchIndex::open(xdg_dirs.get_cache_home().join("search").join(backend))
+    }
+
+    /// Every indexed page
+    pub fn pages(&self) -> Vec<Page> {
+        self.pages.keys().cloned().collect()
+    }
+
+    /// Whether the page has been indexed since it was last modified. Pages without modification
+    /// stamps are current once they've been indexed.
+    pub fn is_current(&self, page: &Page, modified: Option<u64>) -> bool {
+        match self.pages.get(page) {
+            Some(indexed) => modified.is_none() || indexed.modified == modified,
+            None => false
+        }
+    }
+
+    /// Replace what's indexed for a page. Nothing is searchable until `commit`, or saved until
+    /// `save` or `commit`.
+    pub fn index_page(&mut self, page: &Page, modified: Option<u64>, entries: &[Entry]) {
+        let entries = entries.iter().map(|entry| (entry.id, words(&entry.content))).collect();
+        self.pages.insert(page.clone(), IndexedPage {modified, entries});
+        self.unsaved.insert(page.clone());
+        self.dirty = true;
+    }
+
+    /// Drop a page from the index. Nothing is searchable until `commit`, or saved until `save` or
+    /// `commit`.
+    pub fn forget_page(&mut self, page: &Page) {
+        if self.pages.remove(page).is_some() {
+            self.unsaved.insert(page.clone());
+            self.dirty = true;
+        }
+    }
+
+    /// Append the pages changed since they were last written to `pages`, without rebuilding the
+    /// word map. The map on disk is removed, as it's out of step until the next `commit`.
+    pub fn save(&mut self) -> Result<(), StorageError> {
+        let dir = match self.dir {
+            Some(ref dir) => dir,
+            None => {
+                self.unsaved.clear();
+                return Ok(());
+            }
+        };
+        if self.unsaved.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(dir)?;
+        let mut changed = Vec::new();
+        for page in self.unsaved.iter() {
+            match self.pages.get(page) {
+                Some(indexed) => write_page(&mut changed, page, indexed)?,
+                None => writeln!(changed, "gone {}", page.key())?
+            }
+        }
+        match fs::remove_file(dir.join("words.fst")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => ()
+        }
+        fs::OpenOptions::new().create(true).append(true).open(dir.join("pages"))?.write_all(&changed)?;
+        self.unsaved.clear();
+        Ok(())
+    }
+
+    /// Rebuild the word map after pages have changed and write out the whole index
+    pub fn commit(&mut self) -> Result<(), StorageError> {
+        if !self.dirty {
+            return self.save();
+        }
+        let mut builder = MapBuilder::memory();
+        let mut postings = Vec::new();
+        for (idx, (word, entries)) in self.word_postings().into_iter().enumerate() {
+            builder.insert(word, idx as u64).expect("Words are inserted in order");
+            postings.push(entries);
+        }
+        let bytes = builder.into_inner().expect("Building in memory can't fail");
+        if let Some(ref dir) = self.dir {
+            fs::create_dir_all(dir)?;
+            let mut pages = Vec::new();
+            write_pages(&mut pages, &self.pages)?;
+            replace_file(&dir.join("pages"), &pages)?;
+            replace_file(&dir.join("words.fst"), &bytes)?;
+        }
+        self.words = Map::new(bytes).expect("A map that was just built is valid");
+        self.postings = postings;
+        self.dirty = false;
+        self.unsaved.clear();
+        Ok(())
+    }
+
+    /// The entries that could match the query: for every query word, an entry has to use a word
+    /// starting with it
+    pub fn candidates(&self, query: &[String]) -> BTreeSet<(Page, Uuid)> {
+        let mut candidates: Option<BTreeSet<(Page, Uuid)>> = None;
+        for query_word in query {
+            let mut matching = BTreeSet::new();
+            let mut stream = self.words.search(Str::new(query_word).starts_with()).into_stream();
+            while let Some((_, idx)) = stream.next() {
+                matching.extend(self.postings[idx as usize].iter().cloned());
+            }
+            candidates = Some(match candidates {
+                Some(previous) => previous.intersection(&matching).cloned().collect(),
+                None => matching
+            });
+        }
+        candidates.unwrap_or_default()
+    }
+
+    /// Every indexed word with the entries using it, sorted by word
+    fn word_postings(&self) -> BTreeMap<String, BTreeSet<(Page, Uuid)>> {
+        let mut postings: BTreeMap<String, BTreeSet<(Page, Uuid)>> = BTreeMap::new();
+        for (page, indexed) in self.pages.iter() {
+            for (id, words) in indexed.entries.iter() {
+                for word in words {
+                    postings.entry(word.clone()).or_default().insert((page.clone(), *id));
+                }
+            }
+        }
+        postings
+    }
+}
+
+/// Write to a temporary file first so a crash can't leave half an index behind
+fn replace_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+    fs::write(&partial, contents)?;
+    fs::rename(partial, path)
+}
+
+/// Pages are written as a `page <modified> <key>` line, with `-` for pages without modification
+/// stamps, followed by a tab-indented `<id> <words>` line for each entry. A page written again
+/// later in the file replaces the earlier one, and a `gone <key>` line removes it.
+fn write_page<W: Write>(out: &mut W, page: &Page, indexed: &IndexedPage) -> io::Result<()> {
+    let modified = indexed.modified.map(|m| m.to_string()).unwrap_or_else(|| "-".to_string());
+    writeln!(out, "page {} {}", modified, page.key())?;
+    for (id, words) in indexed.entries.iter() {
+        writeln!(out, "\t{} {}", id.hyphenated(), words.join(" "))?;
+    }
+    Ok(())
+}
+
+fn write_pages<W: Write>(out: W, pages: &BTreeMap<Page, IndexedPage>) -> io::Result<()> {
+    let mut out = BufWriter::new(out);
+    for (page, indexed) in pages.iter() {
+        write_page(&mut out, page, indexed)?;
+    }
+    out.flush()
+}
+
+fn read_pages<R: BufRead>(input: R) -> Result<BTreeMap<Page, IndexedPage>, StorageError> {
+    let corrupt = |line: &str| StorageError::Location(format!("the search index is corrupt near '{}'", line));
+    let mut pages = BTreeMap::new();
+    let mut current: Option<(Page, IndexedPage)> = None;
+    for line in input.lines() {
+        let line = line?;
+        if let Some(entry) = line.strip_prefix('\t') {
+            let indexed = &mut current.as_mut().ok_or_else(|| corrupt(&line))?.1;
+            let mut parts = entry.splitn(2, ' ');
+            let id = parts.next().and_then(|id| Uuid::parse_str(id).ok()).ok_or_else(|| corrupt(&line))?;
+            let words = parts.next().unwrap_or("").split(' ').filter(|w| !w.is_empty()).map(String::from).collect();
+            indexed.entries.push((id, words));
+        } else if let Some(key) = line.strip_prefix("gone ") {
+            let page = Page::from_key(key).ok_or_else(|| corrupt(&line))?;
+            if let Some((page, indexed)) = current.take() {
+                pages.insert(page, indexed);
+            }
+            pages.remove(&page);
+        } else {
+            let mut parts = line.splitn(3, ' ');
+            let (modified, key) = match (parts.next(), parts.next(), parts.next()) {
+                (Some("page"), Some(modified), Some(key)) => (modified, key),
+                _ => return Err(corrupt(&line))
+            };
+            let page = Page::from_key(key).ok_or_else(|| corrupt(&line))?;
+            let modified = if modified == "-" { None } else { Some(modified.parse().map_err(|_| corrupt(&line))?) };
+            if let Some((page, indexed)) = current.take() {
+                pages.insert(page, indexed);
+            }
+            current = Some((page, IndexedPage {modified, entries: Vec::new()}));
+        }
+    }
+    if let Some((page, indexed)) = current {
+        pages.insert(page, indexed);
+    }
+    Ok(pages)
+}
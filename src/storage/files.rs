@@ -0,0 +1,328 @@
+use entry::{Entry, ParseError};
+use page::Page;
+use storage::{parse_saved, Storage, StorageError};
+use tz::{self, Timezone};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use chrono::NaiveDate;
+
+/// Days start with a line recording the timezone they were written in
+const TIMEZONE_HEADER: &str = "@tz ";
+
+/// Stores each page as a plain text file with one entry per line, so they can be edited in other
+/// programs. Days are `YYYY/MM/DD.txt`, monthly logs `YYYY/MM/month.txt`, the future log
+/// `future.txt` and collections `collections/<name>.txt`. Days start with an `@tz` line naming the
+/// timezone they were written in.
+///
+/// Older versions kept days in `YYYY-MM-DD.txt` and monthly logs in `YYYY-MM.txt`; those are moved
+/// into place by `upgrade`.
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new<P: Into<PathBuf>>(root: P) -> FileStorage {
+        FileStorage {root: root.into()}
+    }
+
+    /// Storage in `$XDG_CONFIG_HOME/bullet-terminal`
+    pub fn xdg() -> Result<FileStorage, StorageError> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix("bullet-terminal")
+            .map_err(|e| StorageError::Location(e.to_string()))?;
+        Ok(FileStorage::new(xdg_dirs.get_config_home()))
+    }
+
+    fn path(&self, page: &Page) -> PathBuf {
+        self.root.join(match page {
+            Page::Day(date) => format!("{}.txt", date.format("%Y/%m/%d")),
+            Page::Month(date) => format!("{}/month.txt", date.format("%Y/%m")),
+            Page::Future => "future.txt".to_string(),
+            Page::Collection(name) => format!("collections/{}.txt", name),
+        })
+    }
+
+    /// The flat files written by older versions and where each of them belongs now
+    fn legacy_moves(&self) -> Result<Vec<Move>, StorageError> {
+        let mut moves = Vec::new();
+        for name in text_files(&self.root)? {
+            let page = if let Ok(date) = NaiveDate::parse_from_str(&name, "%Y-%m-%d") {
+                Page::Day(date)
+            } else if let Ok(date) = NaiveDate::parse_from_str(&format!("{}-01", name), "%Y-%m-%d") {
+                Page::Month(date)
+            } else {
+                continue
+            };
+            let to = self.path(&page);
+            moves.push(Move {from: self.root.join(format!("{}.txt", name)),
+                             merge: to.exists(),
+                             to});
+        }
+        moves.sort_by(|a, b| a.from.cmp(&b.from));
+        Ok(moves)
+    }
+}
+
+/// A legacy file to be moved into the nested layout
+struct Move {
+    from: PathBuf,
+    to: PathBuf,
+    /// The destination already exists, so the legacy entries are appended to it instead
+    merge: bool,
+}
+
+impl Move {
+    fn describe(&self, root: &Path) -> String {
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).display().to_string();
+        if self.merge {
+            format!("{} → {} (appended to the existing page)", relative(&self.from), relative(&self.to))
+        } else {
+            format!("{} → {}", relative(&self.from), relative(&self.to))
+        }
+    }
+
+    fn apply(&self) -> io::Result<()> {
+        if let Some(parent) = self.to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if !self.merge {
+            return fs::rename(&self.from, &self.to);
+        }
+        let mut legacy = String::new();
+        File::open(&self.from)?.read_to_string(&mut legacy)?;
+        let mut existing = String::new();
+        File::open(&self.to)?.read_to_string(&mut existing)?;
+        let mut f = OpenOptions::new().append(true).open(&self.to)?;
+        if !existing.is_empty() && !existing.ends_with('\n') {
+            f.write_all(b"\n")?;
+        }
+        f.write_all(legacy.as_bytes())?;
+        f.sync_all()?;
+        fs::remove_file(&self.from)
+    }
+}
+
+/// The names of every directory directly inside `dir` that are made up of `len` digits
+fn numbered_dirs(dir: &Path, len: usize) -> Result<Vec<String>, StorageError> {
+    let dirs = match fs::read_dir(dir) {
+        Ok(dirs) => dirs,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into())
+    };
+    let mut names = Vec::new();
+    for dir in dirs {
+        let path = dir?.path();
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            if path.is_dir() && name.len() == len && name.chars().all(|c| c.is_ascii_digit()) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The names (without extension) of every `.txt` file directly inside `dir`
+fn text_files(dir: &Path) -> Result<Vec<String>, StorageError> {
+    let files = match fs::read_dir(dir) {
+        Ok(files) => files,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into())
+    };
+    let mut names = Vec::new();
+    for file in files {
+        let path = file?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "txt") {
+            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                names.push(stem.to_string());
+            }
+        }
+    }
+    Ok(names)
+}
+
+impl Storage for FileStorage {
+    fn load(&self, page: &Page) -> Result<Vec<Entry>, StorageError> {
+        let f = match File::open(self.path(page)) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into())
+        };
+        let reader = BufReader::new(f);
+        let mut vec = Vec::new();
+        for (idx, maybe_line) in reader.lines().enumerate() {
+            let line = maybe_line?;
+            if idx == 0 && line.starts_with(TIMEZONE_HEADER) {
+                continue;
+            }
+            vec.push(parse_saved(page, vec.len(), &line)?);
+        }
+        Ok(vec)
+    }
+
+    fn save(&self, page: &Page, entries: &[Entry]) -> Result<(), StorageError> {
+        // Days remember the timezone they were first written in
+        let zone = match page {
+            Page::Day(_) => Some(self.timezone(page)?.unwrap_or_else(|| tz::home().resolved())),
+            _ => None
+        };
+        let path = self.path(page);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut f = BufWriter::new(File::create(path)?);
+        if let Some(zone) = zone {
+            writeln!(f, "{}{}", TIMEZONE_HEADER, zone)?;
+        }
+        for entry in entries.iter() {
+            writeln!(f, "{}", entry)?;
+        }
+        f.flush()?;
+        Ok(())
+    }
+
+    fn timezone(&self, page: &Page) -> Result<Option<Timezone>, StorageError> {
+        let f = match File::open(self.path(page)) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into())
+        };
+        let mut first = String::new();
+        BufReader::new(f).read_line(&mut first)?;
+        if !first.starts_with(TIMEZONE_HEADER) {
+            return Ok(None);
+        }
+        Timezone::from_str(&first[TIMEZONE_HEADER.len()..])
+            .map(Some)
+            .map_err(|e| StorageError::Parse(ParseError::InvalidEntry(e)))
+    }
+
+    fn list(&self) -> Result<Vec<Page>, StorageError> {
+        let mut pages = Vec::new();
+        if self.root.join("future.txt").is_file() {
+            pages.push(Page::Future);
+        }
+        for year in numbered_dirs(&self.root, 4)? {
+            let year_dir = self.root.join(&year);
+            for month in numbered_dirs(&year_dir, 2)? {
+                let month_dir = year_dir.join(&month);
+                for name in text_files(&month_dir)? {
+                    let page = if name == "month" {
+                        NaiveDate::parse_from_str(&format!("{}-{}-01", year, month), "%Y-%m-%d").map(Page::Month)
+                    } else {
+                        NaiveDate::parse_from_str(&format!("{}-{}-{}", year, month, name), "%Y-%m-%d").map(Page::Day)
+                    };
+                    if let Ok(page) = page {
+                        pages.push(page);
+                    }
+                }
+            }
+        }
+        for name in text_files(&self.root.join("collections"))? {
+            pages.push(Page::Collection(name));
+        }
+        pages.sort();
+        Ok(pages)
+    }
+
+    fn delete(&self, page: &Page) -> Result<(), StorageError> {
+        match fs::remove_file(self.path(page)) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result.map_err(StorageError::from)
+        }
+    }
+
+    fn exists(&self, page: &Page) -> Result<bool, StorageError> {
+        Ok(self.path(page).is_file())
+    }
+
+    fn modified(&self, page: &Page) -> Result<Option<u64>, StorageError> {
+        let modified = match fs::metadata(self.path(page)) {
+            Ok(metadata) => metadata.modified()?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into())
+        };
+        Ok(modified.duration_since(UNIX_EPOCH).ok().map(|since| since.as_nanos() as u64))
+    }
+
+    fn pending_upgrade(&self) -> Result<Vec<String>, StorageError> {
+        Ok(self.legacy_moves()?.iter().map(|m| m.describe(&self.root)).collect())
+    }
+
+    fn upgrade(&self) -> Result<(), StorageError> {
+        for m in self.legacy_moves()? {
+            m.apply()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entry::EntryState;
+    use storage::tests::{day, round_trip, TempDir};
+
+    #[test]
+    fn pages_round_trip() {
+        let dir = TempDir::new();
+        round_trip(&FileStorage::new(&dir.0));
+    }
+
+    #[test]
+    fn pages_are_laid_out_by_date() {
+        let dir = TempDir::new();
+        let storage = FileStorage::new(&dir.0);
+        tz::set_home(Timezone::from_str("+02:00").unwrap());
+        let entries = vec![Entry::new("Dinner", EntryState::Event)];
+        storage.save(&Page::Day(day(2024, 5, 3)), &entries).unwrap();
+        storage.save(&Page::month_of(day(2024, 5, 3)), &[]).unwrap();
+        storage.save(&Page::Collection("Reading list".to_string()), &[]).unwrap();
+        assert_eq!(fs::read_to_string(dir.0.join("2024/05/03.txt")).unwrap(), format!("@tz +02:00\n{}\n", entries[0]));
+        assert!(dir.0.join("2024/05/month.txt").is_file());
+        assert!(dir.0.join("collections/Reading list.txt").is_file());
+        // Stray files and directories aren't pages
+        fs::write(dir.0.join("2024/05/notes.txt"), "").unwrap();
+        fs::create_dir_all(dir.0.join("backup/05")).unwrap();
+        assert_eq!(storage.list().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn legacy_pages_are_moved_into_place() {
+        let dir = TempDir::new();
+        let storage = FileStorage::new(&dir.0);
+        fs::write(dir.0.join("2024-05-03.txt"), ". Pay rent\n").unwrap();
+        fs::write(dir.0.join("2024-05.txt"), ". Taxes\n").unwrap();
+        // A day written both before and after the layout changed, without a final newline
+        fs::create_dir_all(dir.0.join("2024/05")).unwrap();
+        fs::write(dir.0.join("2024-05-04.txt"), "- Written first\n").unwrap();
+        fs::write(dir.0.join("2024/05/04.txt"), "@tz +02:00\no Written later").unwrap();
+        // Not pages
+        fs::write(dir.0.join("future.txt"), "").unwrap();
+        fs::write(dir.0.join("notes.txt"), "").unwrap();
+
+        let moves = storage.legacy_moves().unwrap();
+        let described: Vec<String> = moves.iter().map(|m| m.describe(&dir.0)).collect();
+        assert_eq!(described, vec![
+            "2024-05-03.txt → 2024/05/03.txt".to_string(),
+            "2024-05-04.txt → 2024/05/04.txt (appended to the existing page)".to_string(),
+            "2024-05.txt → 2024/05/month.txt".to_string(),
+        ]);
+        assert_eq!(storage.pending_upgrade().unwrap(), described);
+        for m in &moves {
+            m.apply().unwrap();
+        }
+
+        assert!(storage.pending_upgrade().unwrap().is_empty());
+        assert!(!dir.0.join("2024-05-04.txt").exists());
+        assert_eq!(fs::read_to_string(dir.0.join("2024/05/04.txt")).unwrap(), "@tz +02:00\no Written later\n- Written first\n");
+        let contents = |page| storage.load(&page).unwrap().into_iter().map(|entry| entry.content).collect::<Vec<String>>();
+        assert_eq!(contents(Page::Day(day(2024, 5, 3))), vec!["Pay rent"]);
+        assert_eq!(contents(Page::Day(day(2024, 5, 4))), vec!["Written later", "Written first"]);
+        assert_eq!(contents(Page::month_of(day(2024, 5, 1))), vec!["Taxes"]);
+        assert_eq!(storage.timezone(&Page::Day(day(2024, 5, 4))).unwrap(), Some(Timezone::from_str("+02:00").unwrap()));
+    }
+}
@@ -0,0 +1,162 @@
+use entry::Entry;
+use page::Page;
+use search::{self, SearchIndex};
+use storage::{Storage, StorageError};
+use tz::Timezone;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+/// Wraps another storage, keeping a search index in step with every page saved or deleted
+/// through it. Pages changed behind the journal's back are picked up by their modification stamps
+/// before every search, which is also when a new or thrown away index is first built.
+pub struct IndexedStorage<S> {
+    inner: S,
+    index: RefCell<SearchIndex>,
+}
+
+impl<S: Storage> IndexedStorage<S> {
+    pub fn new(inner: S, index: SearchIndex) -> IndexedStorage<S> {
+        IndexedStorage {inner, index: RefCell::new(index)}
+    }
+
+    /// Index every page that's new or changed since it was last indexed and forget deleted ones.
+    /// Pages that can't be read are left as they were, to be tried again by the next search.
+    fn refresh(&self) -> Result<(), StorageError> {
+        let pages: BTreeSet<Page> = self.inner.list()?.into_iter().collect();
+        let mut index = self.index.borrow_mut();
+        for page in index.pages() {
+            if !pages.contains(&page) {
+                index.forget_page(&page);
+            }
+        }
+        for page in pages {
+            let modified = self.inner.modified(&page)?;
+            if !index.is_current(&page, modified) {
+                if let Ok(entries) = self.inner.load(&page) {
+                    index.index_page(&page, modified, &entries);
+                }
+            }
+        }
+        index.commit()
+    }
+}
+
+impl<S: Storage> Storage for IndexedStorage<S> {
+    fn load(&self, page: &Page) -> Result<Vec<Entry>, StorageError> {
+        self.inner.load(page)
+    }
+
+    fn save(&self, page: &Page, entries: &[Entry]) -> Result<(), StorageError> {
+        self.inner.save(page, entries)?;
+        let mut index = self.index.borrow_mut();
+        index.index_page(page, self.inner.modified(page)?, entries);
+        // The word map is rebuilt by the next search
+        index.save()
+    }
+
+    fn list(&self) -> Result<Vec<Page>, StorageError> {
+        self.inner.list()
+    }
+
+    fn delete(&self, page: &Page) -> Result<(), StorageError> {
+        self.inner.delete(page)?;
+        let mut index = self.index.borrow_mut();
+        index.forget_page(page);
+        index.save()
+    }
+
+    fn exists(&self, page: &Page) -> Result<bool, StorageError> {
+        self.inner.exists(page)
+    }
+
+    fn timezone(&self, page: &Page) -> Result<Option<Timezone>, StorageError> {
+        self.inner.timezone(page)
+    }
+
+    fn modified(&self, page: &Page) -> Result<Option<u64>, StorageError> {
+        self.inner.modified(page)
+    }
+
+    fn search(&self, query: &str) -> Result<Vec<(Page, Entry)>, StorageError> {
+        self.refresh()?;
+        let query = search::words(query);
+        let mut by_page: BTreeMap<Page, BTreeSet<Uuid>> = BTreeMap::new();
+        for (page, id) in self.index.borrow().candidates(&query) {
+            by_page.entry(page).or_default().insert(id);
+        }
+        // Only the pages holding candidates are read
+        let mut found = Vec::new();
+        for (page, ids) in by_page {
+            // A page that can't be read is left out, rather than failing every search
+            let entries = self.inner.load(&page).unwrap_or_default();
+            for entry in entries.into_iter().filter(|entry| ids.contains(&entry.id)) {
+                found.push((page.clone(), entry));
+            }
+        }
+        Ok(search::rank(&query, found))
+    }
+
+    fn pending_upgrade(&self) -> Result<Vec<String>, StorageError> {
+        self.inner.pending_upgrade()
+    }
+
+    fn upgrade(&self) -> Result<(), StorageError> {
+        self.inner.upgrade()?;
+        self.refresh()
+    }
+
+    fn entries(&self) -> Result<Vec<(Page, Entry)>, StorageError> {
+        self.inner.entries()
+    }
+
+    fn find(&self, id: Uuid) -> Result<Option<(Page, Entry)>, StorageError> {
+        self.inner.find(id)
+    }
+
+    fn entries_between(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<(NaiveDate, Entry)>, StorageError> {
+        self.inner.entries_between(start, end)
+    }
+
+    fn scheduled_between(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<(Page, Entry)>, StorageError> {
+        self.inner.scheduled_between(start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entry::EntryState;
+    use storage::FileStorage;
+    use storage::tests::{day, TempDir};
+
+    use std::fs::{self, OpenOptions};
+
+    fn contents(found: Vec<(Page, Entry)>) -> Vec<String> {
+        found.into_iter().map(|(_, entry)| entry.content).collect()
+    }
+
+    #[test]
+    fn damaged_indexes_are_rebuilt_by_the_next_search() {
+        let dir = TempDir::new();
+        let journal = dir.0.join("journal");
+        let index = dir.0.join("index");
+        {
+            let storage = IndexedStorage::new(FileStorage::new(&journal), SearchIndex::open(&index).unwrap());
+            storage.save(&Page::Day(day(2024, 5, 3)), &[Entry::new("Send the invoices", EntryState::Incomplete)]).unwrap();
+            storage.save(&Page::Day(day(2024, 5, 4)), &[Entry::new("Chase the invoices", EntryState::Incomplete)]).unwrap();
+            assert_eq!(contents(storage.search("invoice").unwrap()).len(), 2);
+        }
+        for name in ["pages", "words.fst"] {
+            let file = OpenOptions::new().write(true).open(index.join(name)).unwrap();
+            file.set_len(file.metadata().unwrap().len() / 2).unwrap();
+        }
+        // Opening reads none of the pages, so one that can't be read doesn't stop it
+        fs::write(journal.join("2024/05/04.txt"), "garbage\n").unwrap();
+        let storage = IndexedStorage::new(FileStorage::new(&journal), SearchIndex::open(&index).unwrap());
+        assert_eq!(contents(storage.search("invoice").unwrap()), vec!["Send the invoices"]);
+    }
+}
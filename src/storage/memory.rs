@@ -0,0 +1,58 @@
+use entry::Entry;
+use page::Page;
+use storage::{Storage, StorageError};
+use tz::{self, Timezone};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// Keeps every page in memory. Nothing survives the process, which makes it handy for tests and
+/// for trying things out without touching the real journal.
+#[derive(Default)]
+pub struct MemoryStorage {
+    pages: RefCell<BTreeMap<Page, Vec<Entry>>>,
+    zones: RefCell<BTreeMap<Page, Timezone>>,
+}
+
+impl Storage for MemoryStorage {
+    fn load(&self, page: &Page) -> Result<Vec<Entry>, StorageError> {
+        Ok(self.pages.borrow().get(page).cloned().unwrap_or_default())
+    }
+
+    fn save(&self, page: &Page, entries: &[Entry]) -> Result<(), StorageError> {
+        if let Page::Day(_) = page {
+            self.zones.borrow_mut().entry(page.clone()).or_insert_with(|| tz::home().resolved());
+        }
+        self.pages.borrow_mut().insert(page.clone(), entries.to_vec());
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Page>, StorageError> {
+        Ok(self.pages.borrow().keys().cloned().collect())
+    }
+
+    fn delete(&self, page: &Page) -> Result<(), StorageError> {
+        self.pages.borrow_mut().remove(page);
+        self.zones.borrow_mut().remove(page);
+        Ok(())
+    }
+
+    fn exists(&self, page: &Page) -> Result<bool, StorageError> {
+        Ok(self.pages.borrow().contains_key(page))
+    }
+
+    fn timezone(&self, page: &Page) -> Result<Option<Timezone>, StorageError> {
+        Ok(self.zones.borrow().get(page).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::tests::round_trip;
+
+    #[test]
+    fn pages_round_trip() {
+        round_trip(&MemoryStorage::default());
+    }
+}
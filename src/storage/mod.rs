@@ -0,0 +1,313 @@
+//! Persistence for journal pages. The views only talk to the `Storage` trait, so the backend can
+//! be swapped out without touching them.
+
+mod files;
+mod indexed;
+mod memory;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use self::files::FileStorage;
+pub use self::indexed::IndexedStorage;
+pub use self::memory::MemoryStorage;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStorage;
+
+use entry::{Entry, EntryState, ParseError};
+use page::Page;
+use search::{self, SearchIndex};
+use tz::{self, Timezone};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+
+use chrono::NaiveDate;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Parse(ParseError),
+    /// The storage location couldn't be found or created
+    Location(String),
+    /// The database backend failed or holds something unexpected
+    #[cfg(feature = "sqlite")]
+    Database(String),
+}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> StorageError {
+        StorageError::Io(error)
+    }
+}
+
+impl From<ParseError> for StorageError {
+    fn from(error: ParseError) -> StorageError {
+        StorageError::Parse(error)
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Io(error) => write!(f, "{}", error),
+            StorageError::Parse(error) => write!(f, "{}", error),
+            StorageError::Location(msg) => write!(f, "Unable to find the journal: {}", msg),
+            #[cfg(feature = "sqlite")]
+            StorageError::Database(msg) => write!(f, "Journal database error: {}", msg),
+        }
+    }
+}
+
+/// A place journal pages are kept
+pub trait Storage {
+    /// Load all entries of a page. Pages that haven't been saved yet are empty.
+    fn load(&self, page: &Page) -> Result<Vec<Entry>, StorageError>;
+
+    /// Replace the entries of a page, creating it if needed
+    fn save(&self, page: &Page, entries: &[Entry]) -> Result<(), StorageError>;
+
+    /// Every saved page, sorted
+    fn list(&self) -> Result<Vec<Page>, StorageError>;
+
+    /// Remove a page and all of its entries. Deleting a page that doesn't exist is not an error.
+    fn delete(&self, page: &Page) -> Result<(), StorageError>;
+
+    /// Whether the page has been saved, even if it has no entries
+    fn exists(&self, page: &Page) -> Result<bool, StorageError>;
+
+    /// The timezone a daily page was written in. `None` for pages that aren't tied to a day and
+    /// for days written before timezones were recorded.
+    fn timezone(&self, page: &Page) -> Result<Option<Timezone>, StorageError>;
+
+    /// A stamp that changes whenever the page does, for storage that can be changed behind the
+    /// journal's back. `None` when the storage doesn't keep them.
+    fn modified(&self, _page: &Page) -> Result<Option<u64>, StorageError> {
+        Ok(None)
+    }
+
+    /// Every entry with a word starting with each word of the query, best matches first
+    fn search(&self, query: &str) -> Result<Vec<(Page, Entry)>, StorageError> {
+        Ok(search::rank(&search::words(query), self.entries()?))
+    }
+
+    /// Describe the work needed to bring data written by older versions up to date, one line per
+    /// change. Nothing is changed until `upgrade` is called.
+    fn pending_upgrade(&self) -> Result<Vec<String>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    /// Carry out the changes described by `pending_upgrade`
+    fn upgrade(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// Every entry of every page, along with its page. Sorted by page, then by position.
+    fn entries(&self) -> Result<Vec<(Page, Entry)>, StorageError> {
+        let mut found = Vec::new();
+        for page in self.list()? {
+            for entry in self.load(&page)? {
+                found.push((page.clone(), entry));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Find an entry by its id, along with the page it's on
+    fn find(&self, id: Uuid) -> Result<Option<(Page, Entry)>, StorageError> {
+        for page in self.list()? {
+            if let Some(entry) = self.load(&page)?.into_iter().find(|entry| entry.id == id) {
+                return Ok(Some((page, entry)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Every entry of the daily pages for days in `[start, end)`, along with its day. Sorted by day.
+    fn entries_between(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<(NaiveDate, Entry)>, StorageError> {
+        let mut found = Vec::new();
+        for page in self.list()? {
+            if let Page::Day(day) = page {
+                if day >= start && day < end {
+                    for entry in self.load(&page)? {
+                        found.push((day, entry));
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Every entry scheduled (<) for a day in `[start, end)`, along with the page it was scheduled
+    /// from. Sorted by the day it's scheduled for.
+    fn scheduled_between(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<(Page, Entry)>, StorageError> {
+        let mut found = Vec::new();
+        for page in self.list()? {
+            for entry in self.load(&page)? {
+                if let Some(day) = scheduled_day(&entry) {
+                    if day >= start && day < end {
+                        found.push((page.clone(), entry));
+                    }
+                }
+            }
+        }
+        found.sort_by_key(|(_, entry)| scheduled_day(entry));
+        Ok(found)
+    }
+}
+
+/// Parse the line at `position` of a saved page. Lines written before entries had ids are given
+/// one made from the page and position, so it's the same every time the page is loaded until the
+/// page is saved and the id is written out.
+pub fn parse_saved(page: &Page, position: usize, line: &str) -> Result<Entry, ParseError> {
+    Entry::parse(line, || Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("{:?}/{}", page, position).as_bytes()))
+}
+
+/// The day (in the home timezone) an entry is scheduled for, if it's scheduled at all
+pub fn scheduled_day(entry: &Entry) -> Option<NaiveDate> {
+    match entry.state {
+        EntryState::Scheduled(ref when) => Some(tz::home().date_of(when)),
+        _ => None
+    }
+}
+
+/// Pages that couldn't be read, with the reason for each
+pub type Unreadable = Vec<(Page, StorageError)>;
+
+/// Every entry of the pages picked by `wanted`, along with its page. Sorted by page, then by
+/// position. Pages that can't be read, like hand-edited files with a broken line, don't stop the
+/// rest: they're returned alongside instead.
+pub fn readable_entries<F: Fn(&Page) -> bool>(storage: &dyn Storage, wanted: F) -> Result<(Vec<(Page, Entry)>, Unreadable), StorageError> {
+    let mut found = Vec::new();
+    let mut unreadable = Vec::new();
+    for page in storage.list()?.into_iter().filter(|page| wanted(page)) {
+        match storage.load(&page) {
+            Ok(entries) => found.extend(entries.into_iter().map(|entry| (page.clone(), entry))),
+            Err(e) => unreadable.push((page, e))
+        }
+    }
+    Ok((found, unreadable))
+}
+
+thread_local! {
+    static STORAGE: RefCell<Option<Rc<dyn Storage>>> = RefCell::new(None);
+}
+
+/// Set the storage used by every view. Must be called before any views are built.
+pub fn install(storage: Rc<dyn Storage>) {
+    STORAGE.with(|current| *current.borrow_mut() = Some(storage));
+}
+
+/// The storage installed with `install`
+pub fn current() -> Rc<dyn Storage> {
+    STORAGE.with(|current| current.borrow().clone().expect("No storage has been installed"))
+}
+
+/// Pick the storage backend from `$BULLET_TERMINAL_STORAGE`: `files` (the default) keeps the
+/// journal in the XDG config directory, `memory` is a scratch journal thrown away on exit and
+/// `sqlite` (when built with the `sqlite` feature) keeps it in a database in the same directory.
+/// Every backend is wrapped to keep a search index, which is cached in the XDG cache directory.
+pub fn from_env() -> Result<Rc<dyn Storage>, StorageError> {
+    match ::std::env::var("BULLET_TERMINAL_STORAGE").as_ref().map(String::as_str) {
+        Err(_) | Ok("files") => Ok(Rc::new(IndexedStorage::new(FileStorage::xdg()?, SearchIndex::xdg("files")?))),
+        Ok("memory") => Ok(Rc::new(IndexedStorage::new(MemoryStorage::default(), SearchIndex::in_memory()))),
+        #[cfg(feature = "sqlite")]
+        Ok("sqlite") => Ok(Rc::new(IndexedStorage::new(SqliteStorage::xdg()?, SearchIndex::xdg("sqlite")?))),
+        Ok(other) => Err(StorageError::Location(format!("unknown storage backend '{}'", other))),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A directory of its own under the system's temporary directory, removed when dropped
+    pub struct TempDir(pub PathBuf);
+
+    impl TempDir {
+        pub fn new() -> TempDir {
+            let dir = ::std::env::temp_dir().join(format!("bullet-terminal-test-{}", Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    pub fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn lines(entries: &[Entry]) -> Vec<String> {
+        entries.iter().map(Entry::to_string).collect()
+    }
+
+    /// Save, load, list and delete pages of every kind, checking what comes back
+    pub fn round_trip(storage: &dyn Storage) {
+        tz::set_home(Timezone::from_str("+02:00").unwrap());
+        assert!(storage.list().unwrap().is_empty());
+        assert!(storage.load(&Page::Future).unwrap().is_empty());
+        assert!(!storage.exists(&Page::Future).unwrap());
+        storage.delete(&Page::Future).unwrap();
+
+        let rent = Entry::new("Pay rent", EntryState::Incomplete);
+        let scheduled = rent.copy(EntryState::Scheduled(tz::anchor(day(2024, 5, 10))));
+        let mut nested = Entry::new("Two are overdue #money", EntryState::Note);
+        nested.depth = 1;
+        let third = vec![rent.clone(), nested, scheduled.clone()];
+        let fourth = vec![Entry::new("Dinner", EntryState::Event)];
+        let later = vec![Entry::new("Renew the passport", EntryState::Scheduled(tz::anchor(day(2024, 6, 1))))];
+        let books = vec![Entry::new("Dune", EntryState::Note)];
+        storage.save(&Page::Day(day(2024, 5, 3)), &third).unwrap();
+        storage.save(&Page::Day(day(2024, 5, 4)), &fourth).unwrap();
+        storage.save(&Page::month_of(day(2024, 5, 1)), &later).unwrap();
+        storage.save(&Page::Future, &[]).unwrap();
+        storage.save(&Page::Collection("Reading list".to_string()), &books).unwrap();
+
+        assert_eq!(storage.list().unwrap(), vec![
+            Page::Day(day(2024, 5, 3)),
+            Page::Day(day(2024, 5, 4)),
+            Page::Month(day(2024, 5, 1)),
+            Page::Future,
+            Page::Collection("Reading list".to_string()),
+        ]);
+        assert_eq!(lines(&storage.load(&Page::Day(day(2024, 5, 3))).unwrap()), lines(&third));
+        // Even empty pages exist once they're saved
+        assert!(storage.exists(&Page::Future).unwrap());
+        assert!(!storage.exists(&Page::Day(day(2024, 5, 5))).unwrap());
+        assert_eq!(lines(&storage.load(&Page::Collection("Reading list".to_string())).unwrap()), lines(&books));
+        assert_eq!(storage.timezone(&Page::Day(day(2024, 5, 3))).unwrap(), Some(Timezone::from_str("+02:00").unwrap()));
+        assert_eq!(storage.timezone(&Page::Future).unwrap(), None);
+
+        let between: Vec<(NaiveDate, String)> = storage.entries_between(day(2024, 5, 4), day(2024, 6, 1)).unwrap()
+            .into_iter().map(|(day, entry)| (day, entry.content)).collect();
+        assert_eq!(between, vec![(day(2024, 5, 4), "Dinner".to_string())]);
+        let scheduled_between: Vec<(Page, Uuid)> = storage.scheduled_between(day(2024, 5, 1), day(2024, 7, 1)).unwrap()
+            .into_iter().map(|(page, entry)| (page, entry.id)).collect();
+        assert_eq!(scheduled_between, vec![(Page::Day(day(2024, 5, 3)), scheduled.id), (Page::Month(day(2024, 5, 1)), later[0].id)]);
+        assert!(storage.scheduled_between(day(2024, 5, 11), day(2024, 6, 1)).unwrap().is_empty());
+        assert_eq!(storage.find(rent.id).unwrap().map(|(page, _)| page), Some(Page::Day(day(2024, 5, 3))));
+
+        // Saving again replaces the page, and keeps the timezone it was first written in
+        tz::set_home(Timezone::from_str("-05:00").unwrap());
+        storage.save(&Page::Day(day(2024, 5, 3)), &third[..1]).unwrap();
+        assert_eq!(lines(&storage.load(&Page::Day(day(2024, 5, 3))).unwrap()), lines(&third[..1]));
+        assert_eq!(storage.timezone(&Page::Day(day(2024, 5, 3))).unwrap(), Some(Timezone::from_str("+02:00").unwrap()));
+
+        storage.delete(&Page::Day(day(2024, 5, 3))).unwrap();
+        storage.delete(&Page::Collection("Reading list".to_string())).unwrap();
+        assert!(storage.load(&Page::Day(day(2024, 5, 3))).unwrap().is_empty());
+        assert!(!storage.exists(&Page::Day(day(2024, 5, 3))).unwrap());
+        assert_eq!(storage.list().unwrap(), vec![Page::Day(day(2024, 5, 4)), Page::Month(day(2024, 5, 1)), Page::Future]);
+        assert_eq!(storage.find(rent.id).unwrap().map(|(page, _)| page), None);
+    }
+}
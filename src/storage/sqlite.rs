@@ -0,0 +1,379 @@
+use entry::Entry;
+use page::Page;
+use storage::{parse_saved, scheduled_day, FileStorage, Storage, StorageError};
+use tz::{self, Timezone};
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{Duration, NaiveDate};
+use rusqlite::{self, params, Connection, OptionalExtension};
+use uuid::Uuid;
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(error: rusqlite::Error) -> StorageError {
+        StorageError::Database(error.to_string())
+    }
+}
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS pages (
+        id INTEGER PRIMARY KEY,
+        -- day, month, future or collection
+        kind TEXT NOT NULL,
+        -- YYYY-MM-DD for days and the first day of the month for monthly logs
+        date TEXT,
+        -- only set for collections
+        name TEXT,
+        -- the timezone a day was first written in
+        timezone TEXT,
+        UNIQUE (kind, date, name)
+    );
+    CREATE TABLE IF NOT EXISTS entries (
+        page_id INTEGER NOT NULL REFERENCES pages (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        -- the entry exactly as it'd be written to a text file
+        line TEXT NOT NULL,
+        -- the entry's id, which is also part of the line
+        uuid TEXT,
+        -- the storage tag of the state without any embedded date: . - o < > x
+        state TEXT NOT NULL,
+        -- the date and UTC offset embedded in scheduled (<) and collected (>) states
+        state_date TEXT,
+        content TEXT NOT NULL,
+        PRIMARY KEY (page_id, position)
+    );
+    CREATE INDEX IF NOT EXISTS pages_by_date ON pages (kind, date);
+    CREATE INDEX IF NOT EXISTS entries_by_state ON entries (state, state_date);
+";
+
+/// Keeps the journal in a SQLite database, so questions spanning many pages (what's scheduled for
+/// next week?) are a single query instead of reading every file.
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStorage, StorageError> {
+        SqliteStorage::with_connection(Connection::open(path)?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<SqliteStorage, StorageError> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        // Databases created by older versions lack the newer columns
+        add_missing_column(&conn, "pages", "timezone")?;
+        add_missing_column(&conn, "entries", "uuid")?;
+        conn.execute_batch("CREATE INDEX IF NOT EXISTS entries_by_uuid ON entries (uuid);")?;
+        Ok(SqliteStorage {conn})
+    }
+
+    /// Storage in `$XDG_CONFIG_HOME/bullet-terminal/journal.sqlite3`. A new database starts with
+    /// the journal kept in files in the same directory, which are left as they are.
+    pub fn xdg() -> Result<SqliteStorage, StorageError> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix("bullet-terminal")
+            .map_err(|e| StorageError::Location(e.to_string()))?;
+        let path = xdg_dirs.place_config_file("journal.sqlite3")?;
+        if path.exists() {
+            return SqliteStorage::open(path);
+        }
+        let storage = SqliteStorage::open(&path)?;
+        if let Err(e) = storage.import(&FileStorage::new(xdg_dirs.get_config_home())) {
+            // Try again next time rather than leave half a journal behind
+            drop(storage);
+            let _ = fs::remove_file(&path);
+            return Err(e);
+        }
+        Ok(storage)
+    }
+
+    /// Copy every page of another journal, like one kept in files, into the database, replacing
+    /// the pages it already has. Days keep the timezone they were written in.
+    pub fn import(&self, from: &dyn Storage) -> Result<(), StorageError> {
+        if !from.pending_upgrade()?.is_empty() {
+            return Err(StorageError::Location("the journal being copied was written by an older version. \
+                                               Open it with the files backend to upgrade it first".to_string()));
+        }
+        for page in from.list()? {
+            self.save(&page, &from.load(&page)?)?;
+            let (kind, date, name) = page_columns(&page);
+            self.conn.execute("UPDATE pages SET timezone = ?4 WHERE kind = ?1 AND date IS ?2 AND name IS ?3",
+                              params![kind, date, name, from.timezone(&page)?.map(|zone| zone.to_string())])?;
+        }
+        Ok(())
+    }
+
+    /// Every entry matching `filter`, which follows the SELECT and can refer to the `pages` and
+    /// `entries` tables
+    fn query_entries<P: rusqlite::Params>(&self, filter: &str, params: P) -> Result<Vec<(Page, Entry)>, StorageError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT pages.kind, pages.date, pages.name, entries.position, entries.line
+             FROM entries JOIN pages ON pages.id = entries.page_id
+             {}", filter))?;
+        let rows = stmt.query_map(params, |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?,
+                                                     row.get::<_, i64>(3)?, row.get::<_, String>(4)?)))?;
+        let mut found = Vec::new();
+        for row in rows {
+            let (kind, date, name, position, line) = row?;
+            let page = page_from_columns(&kind, date, name)?;
+            let entry = parse_saved(&page, position as usize, &line)?;
+            found.push((page, entry));
+        }
+        Ok(found)
+    }
+
+    fn page_id(&self, page: &Page) -> Result<Option<i64>, StorageError> {
+        let (kind, date, name) = page_columns(page);
+        Ok(self.conn.query_row(
+            "SELECT id FROM pages WHERE kind = ?1 AND date IS ?2 AND name IS ?3",
+            params![kind, date, name],
+            |row| row.get(0)).optional()?)
+    }
+}
+
+/// Add a nullable text column to a table unless it's already there
+fn add_missing_column(conn: &Connection, table: &str, column: &str) -> Result<(), StorageError> {
+    let exists: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2", params![table, column], |row| row.get(0))?;
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} TEXT;", table, column))?;
+    }
+    Ok(())
+}
+
+/// The kind, date and name columns identifying a page
+fn page_columns(page: &Page) -> (&'static str, Option<String>, Option<String>) {
+    match page {
+        Page::Day(date) => ("day", Some(date.format("%Y-%m-%d").to_string()), None),
+        Page::Month(date) => ("month", Some(date.format("%Y-%m-%d").to_string()), None),
+        Page::Future => ("future", None, None),
+        Page::Collection(name) => ("collection", None, Some(name.clone())),
+    }
+}
+
+fn page_from_columns(kind: &str, date: Option<String>, name: Option<String>) -> Result<Page, StorageError> {
+    let date = || -> Result<NaiveDate, StorageError> {
+        let date = date.clone().ok_or_else(|| StorageError::Database(format!("{} page without a date", kind)))?;
+        NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+            .map_err(|e| StorageError::Database(format!("invalid page date '{}': {}", date, e)))
+    };
+    match kind {
+        "day" => Ok(Page::Day(date()?)),
+        "month" => Ok(Page::Month(date()?)),
+        "future" => Ok(Page::Future),
+        "collection" => name.map(Page::Collection)
+            .ok_or_else(|| StorageError::Database("collection page without a name".to_string())),
+        _ => Err(StorageError::Database(format!("unknown page kind '{}'", kind)))
+    }
+}
+
+/// Split an entry's storage tag into the bare state tag and its embedded date, if any
+fn state_columns(entry: &Entry) -> (String, Option<String>) {
+    let tag = entry.state.to_string();
+    let (state, date) = tag.split_at(1);
+    (state.to_string(), if date.is_empty() { None } else { Some(date.to_string()) })
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self, page: &Page) -> Result<Vec<Entry>, StorageError> {
+        let id = match self.page_id(page)? {
+            Some(id) => id,
+            None => return Ok(Vec::new())
+        };
+        Ok(self.query_entries("WHERE entries.page_id = ?1 ORDER BY entries.position", params![id])?
+           .into_iter()
+           .map(|(_, entry)| entry)
+           .collect())
+    }
+
+    fn save(&self, page: &Page, entries: &[Entry]) -> Result<(), StorageError> {
+        let tx = self.conn.unchecked_transaction()?;
+        // Days remember the timezone they were first written in
+        let zone = match page {
+            Page::Day(_) => Some(tz::home().resolved().to_string()),
+            _ => None
+        };
+        let id = match self.page_id(page)? {
+            Some(id) => {
+                // Days saved before timezones were recorded get one now, like the files' `@tz` line
+                tx.execute("UPDATE pages SET timezone = ?2 WHERE id = ?1 AND timezone IS NULL", params![id, zone])?;
+                id
+            }
+            None => {
+                let (kind, date, name) = page_columns(page);
+                tx.execute("INSERT INTO pages (kind, date, name, timezone) VALUES (?1, ?2, ?3, ?4)",
+                           params![kind, date, name, zone])?;
+                tx.last_insert_rowid()
+            }
+        };
+        tx.execute("DELETE FROM entries WHERE page_id = ?1", params![id])?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO entries (page_id, position, line, uuid, state, state_date, content)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+            for (position, entry) in entries.iter().enumerate() {
+                let (state, state_date) = state_columns(entry);
+                insert.execute(params![id, position as i64, entry.to_string(), entry.id.to_string(),
+                                       state, state_date, entry.content])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<Page>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT kind, date, name FROM pages")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)))?;
+        let mut pages = Vec::new();
+        for row in rows {
+            let (kind, date, name) = row?;
+            pages.push(page_from_columns(&kind, date, name)?);
+        }
+        pages.sort();
+        Ok(pages)
+    }
+
+    fn delete(&self, page: &Page) -> Result<(), StorageError> {
+        if let Some(id) = self.page_id(page)? {
+            self.conn.execute("DELETE FROM pages WHERE id = ?1", params![id])?;
+        }
+        Ok(())
+    }
+
+    fn exists(&self, page: &Page) -> Result<bool, StorageError> {
+        Ok(self.page_id(page)?.is_some())
+    }
+
+    fn timezone(&self, page: &Page) -> Result<Option<Timezone>, StorageError> {
+        let (kind, date, name) = page_columns(page);
+        let zone: Option<Option<String>> = self.conn.query_row(
+            "SELECT timezone FROM pages WHERE kind = ?1 AND date IS ?2 AND name IS ?3",
+            params![kind, date, name],
+            |row| row.get(0)).optional()?;
+        match zone {
+            Some(Some(zone)) => Timezone::from_str(&zone).map(Some).map_err(StorageError::Database),
+            _ => Ok(None)
+        }
+    }
+
+    fn entries(&self) -> Result<Vec<(Page, Entry)>, StorageError> {
+        let mut found = self.query_entries("ORDER BY pages.id, entries.position", [])?;
+        // Pages are numbered in the order they were created, so sort them like the other backends
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(found)
+    }
+
+    fn find(&self, id: Uuid) -> Result<Option<(Page, Entry)>, StorageError> {
+        // Rows saved before entries had ids have no uuid yet, so they're checked as well
+        Ok(self.query_entries("WHERE entries.uuid = ?1 OR entries.uuid IS NULL", params![id.to_string()])?
+           .into_iter()
+           .find(|(_, entry)| entry.id == id))
+    }
+
+    fn entries_between(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<(NaiveDate, Entry)>, StorageError> {
+        let found = self.query_entries(
+            "WHERE pages.kind = 'day' AND pages.date >= ?1 AND pages.date < ?2
+             ORDER BY pages.date, entries.position",
+            params![start.format("%Y-%m-%d").to_string(), end.format("%Y-%m-%d").to_string()])?;
+        Ok(found.into_iter()
+           .map(|(page, entry)| match page {
+               Page::Day(day) => (day, entry),
+               _ => unreachable!("only day pages were asked for")
+           })
+           .collect())
+    }
+
+    fn scheduled_between(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<(Page, Entry)>, StorageError> {
+        // The stored dates carry their own offsets, so look a day either side of the range and
+        // work out which day they land on in the home timezone afterwards
+        let widened = |date: NaiveDate, days: i64| date.checked_add_signed(Duration::days(days))
+            .unwrap_or(date)
+            .format("%Y-%m-%d")
+            .to_string();
+        let mut found: Vec<(Page, Entry)> = self.query_entries(
+            "WHERE entries.state = '<' AND entries.state_date >= ?1 AND entries.state_date < ?2
+             ORDER BY entries.state_date, pages.date, entries.position",
+            params![widened(start, -1), widened(end, 1)])?
+            .into_iter()
+            .filter(|(_, entry)| match scheduled_day(entry) {
+                Some(day) => day >= start && day < end,
+                None => false
+            })
+            .collect();
+        found.sort_by_key(|(_, entry)| scheduled_day(entry));
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use entry::EntryState;
+    use storage::tests::{day, round_trip, TempDir};
+
+    fn in_memory() -> SqliteStorage {
+        SqliteStorage::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pages_round_trip() {
+        round_trip(&in_memory());
+    }
+
+    #[test]
+    fn old_databases_get_the_new_columns() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            CREATE TABLE pages (id INTEGER PRIMARY KEY, kind TEXT NOT NULL, date TEXT, name TEXT, UNIQUE (kind, date, name));
+            CREATE TABLE entries (
+                page_id INTEGER NOT NULL REFERENCES pages (id) ON DELETE CASCADE,
+                position INTEGER NOT NULL,
+                line TEXT NOT NULL,
+                state TEXT NOT NULL,
+                state_date TEXT,
+                content TEXT NOT NULL,
+                PRIMARY KEY (page_id, position));
+            INSERT INTO pages (id, kind, date) VALUES (1, 'day', '2024-05-03');
+            INSERT INTO entries VALUES (1, 0, '. Pay rent', '.', NULL, 'Pay rent');
+        ").unwrap();
+        let storage = SqliteStorage::with_connection(conn).unwrap();
+        let page = Page::Day(day(2024, 5, 3));
+        assert_eq!(storage.timezone(&page).unwrap(), None);
+        // Rows without a uuid column are still found by the id they're given when loaded
+        let entries = storage.load(&page).unwrap();
+        assert_eq!(storage.find(entries[0].id).unwrap().map(|(_, entry)| entry.content), Some("Pay rent".to_string()));
+
+        // Saving the day records the timezone it's being written in, like the files' `@tz` line
+        tz::set_home(Timezone::from_str("+02:00").unwrap());
+        storage.save(&page, &entries).unwrap();
+        assert_eq!(storage.timezone(&page).unwrap(), Some(Timezone::from_str("+02:00").unwrap()));
+        // but only the first time
+        tz::set_home(Timezone::from_str("-05:00").unwrap());
+        storage.save(&page, &entries).unwrap();
+        assert_eq!(storage.timezone(&page).unwrap(), Some(Timezone::from_str("+02:00").unwrap()));
+    }
+
+    #[test]
+    fn journals_kept_in_files_are_copied_in() {
+        let dir = TempDir::new();
+        let files = FileStorage::new(&dir.0);
+        tz::set_home(Timezone::from_str("+02:00").unwrap());
+        let rent = vec![Entry::new("Pay rent", EntryState::Incomplete)];
+        files.save(&Page::Day(day(2024, 5, 3)), &rent).unwrap();
+        files.save(&Page::Collection("Reading list".to_string()), &[Entry::new("Dune", EntryState::Note)]).unwrap();
+
+        let storage = in_memory();
+        storage.save(&Page::Day(day(2024, 5, 3)), &[Entry::new("Replaced", EntryState::Note)]).unwrap();
+        tz::set_home(Timezone::from_str("-05:00").unwrap());
+        storage.import(&files).unwrap();
+        assert_eq!(storage.list().unwrap(), files.list().unwrap());
+        assert_eq!(storage.load(&Page::Day(day(2024, 5, 3))).unwrap().iter().map(Entry::to_string).collect::<Vec<String>>(),
+                   vec![rent[0].to_string()]);
+        assert_eq!(storage.timezone(&Page::Day(day(2024, 5, 3))).unwrap(), Some(Timezone::from_str("+02:00").unwrap()));
+        assert_eq!(storage.timezone(&Page::Collection("Reading list".to_string())).unwrap(), None);
+
+        // Journals still in the old layout have to be upgraded first
+        fs::write(dir.0.join("2024-05-04.txt"), ". Taxes\n").unwrap();
+        assert!(in_memory().import(&files).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Timezones. Pages are dated in the "home" timezone, which defaults to the system's local time
+//! but can be pinned with `$BULLET_TERMINAL_TZ` so travelling doesn't move "today" around.
+
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use std::cell::Cell;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timezone {
+    /// Whatever the system's local time currently is
+    Local,
+    /// An IANA timezone such as `Europe/Berlin`
+    Named(Tz),
+    /// A fixed offset from UTC such as `+02:00`
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// Parse `local`, an IANA name or a `±HH:MM` offset
+    pub fn from_str(zone: &str) -> Result<Timezone, String> {
+        let zone = zone.trim();
+        if zone.eq_ignore_ascii_case("local") {
+            return Ok(Timezone::Local);
+        }
+        if zone.starts_with('+') || zone.starts_with('-') {
+            return parse_offset(zone)
+                .map(Timezone::Fixed)
+                .ok_or_else(|| format!("Invalid UTC offset '{}'. Offsets look like +02:00", zone));
+        }
+        zone.parse::<Tz>()
+            .map(Timezone::Named)
+            .map_err(|_| format!("Unknown timezone '{}'", zone))
+    }
+
+    /// The current time in this timezone
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        let now = Utc::now();
+        match self {
+            Timezone::Local => now.with_timezone(&Local).fixed_offset(),
+            Timezone::Named(tz) => now.with_timezone(tz).fixed_offset(),
+            Timezone::Fixed(offset) => now.with_timezone(offset),
+        }
+    }
+
+    /// The current date in this timezone
+    pub fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+
+    /// The calendar date of an instant in this timezone
+    pub fn date_of(&self, when: &DateTime<FixedOffset>) -> NaiveDate {
+        self.local_of(when).date()
+    }
+
+    /// The date and time on the clock at an instant in this timezone
+    pub fn local_of(&self, when: &DateTime<FixedOffset>) -> NaiveDateTime {
+        match self {
+            Timezone::Local => when.with_timezone(&Local).naive_local(),
+            Timezone::Named(tz) => when.with_timezone(tz).naive_local(),
+            Timezone::Fixed(offset) => when.with_timezone(offset).naive_local(),
+        }
+    }
+
+    /// The instant `time` happens on `date` in this timezone. Times skipped by a daylight saving
+    /// change are taken as UTC.
+    pub fn at(&self, date: NaiveDate, time: NaiveTime) -> DateTime<FixedOffset> {
+        let naive = date.and_time(time);
+        match self {
+            Timezone::Local => Local.from_local_datetime(&naive).earliest()
+                .map(|when| when.fixed_offset())
+                .unwrap_or_else(|| Utc.from_utc_datetime(&naive).fixed_offset()),
+            Timezone::Named(tz) => tz.from_local_datetime(&naive).earliest()
+                .map(|when| when.fixed_offset())
+                .unwrap_or_else(|| Utc.from_utc_datetime(&naive).fixed_offset()),
+            Timezone::Fixed(offset) => offset.from_utc_datetime(&(naive - *offset)),
+        }
+    }
+
+    /// Pin `Local` down to the offset it currently has, so it still means something after the
+    /// system's timezone changes. Other timezones are returned unchanged.
+    pub fn resolved(&self) -> Timezone {
+        match self {
+            Timezone::Local => Timezone::Fixed(Local::now().offset().fix()),
+            zone => *zone,
+        }
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Timezone::Local => write!(f, "local"),
+            Timezone::Named(tz) => write!(f, "{}", tz.name()),
+            Timezone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    let sign = match offset.get(0..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None
+    };
+    let mut parts = offset.get(1..)?.splitn(2, ':');
+    let hours: i32 = parts.next()?.parse().ok()?;
+    let minutes: i32 = match parts.next() {
+        Some(minutes) => minutes.parse().ok()?,
+        None => 0
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Scheduled and migrated dates are anchored at noon, so they stay on the same calendar day when
+/// viewed from any timezone less than twelve hours away.
+pub fn anchor(date: NaiveDate) -> DateTime<FixedOffset> {
+    home().at(date, NaiveTime::from_hms_opt(12, 0, 0).expect("Noon is a valid time"))
+}
+
+/// Parse a day written as `YYYY-MM-DD`, or as `today`, `yesterday` or `tomorrow` in the home
+/// timezone
+pub fn parse_day(text: &str) -> Result<NaiveDate, String> {
+    let today = home().today();
+    match text {
+        "today" => Ok(today),
+        "yesterday" => Ok(today - Duration::days(1)),
+        "tomorrow" => Ok(today + Duration::days(1)),
+        _ => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date '{}'. Dates look like 2024-01-31 or today", text))
+    }
+}
+
+thread_local! {
+    static HOME: Cell<Timezone> = const { Cell::new(Timezone::Local) };
+}
+
+/// Set the home timezone used to decide what "today" is
+pub fn set_home(zone: Timezone) {
+    HOME.with(|home| home.set(zone));
+}
+
+/// The home timezone, the local time unless changed with `set_home`
+pub fn home() -> Timezone {
+    HOME.with(|home| home.get())
+}
+
+/// Read the home timezone from `$BULLET_TERMINAL_TZ`, defaulting to the local time
+pub fn home_from_env() -> Result<Timezone, String> {
+    match ::std::env::var("BULLET_TERMINAL_TZ") {
+        Ok(zone) => Timezone::from_str(&zone),
+        Err(_) => Ok(Timezone::Local),
+    }
+}
@@ -0,0 +1,167 @@
+use page;
+use views::daily::daily_view;
+use views::monthly::month_entries;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use chrono::{Datelike, Duration, NaiveDate};
+use cursive::{Cursive, Printer, Vec2};
+use cursive::direction::Direction;
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::{ColorStyle, Effect};
+use cursive::view::View;
+use cursive::views::{Dialog, LinearLayout, TextView};
+
+/// Width of the month grid: seven two-character days separated by spaces
+const GRID_WIDTH: usize = 7 * 3 - 1;
+
+type SubmitCallback = Rc<dyn Fn(&mut Cursive, NaiveDate)>;
+
+/// How a day is highlighted in the calendar
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DayMark {
+    /// The day's page has entries
+    Entries,
+    /// The day's page has tasks that are still open
+    OpenTasks,
+}
+
+/// A month-at-a-time calendar for picking a date. Days with entries are shown in bold and days
+/// with open tasks are underlined.
+pub struct CalendarView {
+    selected: NaiveDate,
+    marks: HashMap<NaiveDate, DayMark>,
+    on_submit: Option<SubmitCallback>,
+}
+
+impl CalendarView {
+    pub fn new(selected: NaiveDate) -> CalendarView {
+        CalendarView {selected,
+                      marks: month_marks(selected),
+                      on_submit: None}
+    }
+
+    /// Set the callback run when a date is picked with enter
+    pub fn on_submit<F>(mut self, cb: F) -> CalendarView
+    where F: Fn(&mut Cursive, NaiveDate) + 'static {
+        self.on_submit = Some(Rc::new(cb));
+        self
+    }
+
+    fn select(&mut self, date: NaiveDate) -> EventResult {
+        if page::first_of_month(date) != page::first_of_month(self.selected) {
+            self.marks = month_marks(date);
+        }
+        self.selected = date;
+        EventResult::Consumed(None)
+    }
+
+    fn move_days(&mut self, days: i64) -> EventResult {
+        match self.selected.checked_add_signed(Duration::days(days)) {
+            Some(date) => self.select(date),
+            None => EventResult::Consumed(None)
+        }
+    }
+
+    fn move_months(&mut self, months: i32) -> EventResult {
+        // Stay on the same day of the month where possible, otherwise its last day
+        let month = page::add_months(self.selected, months);
+        let day = page::days_of_month(month).into_iter()
+            .take_while(|day| day.day() <= self.selected.day())
+            .last()
+            .expect("Every month has a first day");
+        self.select(day)
+    }
+
+    fn submit(&self) -> EventResult {
+        let date = self.selected;
+        match self.on_submit {
+            Some(ref cb) => {
+                let cb = cb.clone();
+                EventResult::with_cb(move |s| cb(s, date))
+            }
+            None => EventResult::Ignored
+        }
+    }
+}
+
+impl View for CalendarView {
+    fn draw(&self, printer: &Printer) {
+        let title = self.selected.format("%B %Y").to_string();
+        printer.with_effect(Effect::Bold, |p| {
+            p.print(((GRID_WIDTH.saturating_sub(title.len())) / 2, 0), &title);
+        });
+        printer.print((0, 1), "Mo Tu We Th Fr Sa Su");
+        let first = page::first_of_month(self.selected);
+        let offset = first.weekday().num_days_from_monday() as usize;
+        for day in page::days_of_month(self.selected) {
+            let cell = offset + day.day0() as usize;
+            let pos = ((cell % 7) * 3, 2 + cell / 7);
+            let text = format!("{:>2}", day.day());
+            let print = |p: &Printer| match self.marks.get(&day) {
+                Some(DayMark::OpenTasks) => p.with_effect(Effect::Underline, |p| p.print(pos, &text)),
+                Some(DayMark::Entries) => p.with_effect(Effect::Bold, |p| p.print(pos, &text)),
+                None => p.print(pos, &text),
+            };
+            if day == self.selected {
+                let style = if printer.focused { ColorStyle::highlight() } else { ColorStyle::highlight_inactive() };
+                printer.with_color(style, print);
+            } else {
+                print(printer);
+            }
+        }
+    }
+
+    fn required_size(&mut self, _: Vec2) -> Vec2 {
+        // Title, weekday names and up to six weeks
+        Vec2::new(GRID_WIDTH, 8)
+    }
+
+    fn take_focus(&mut self, _: Direction) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Char('h') | Event::Key(Key::Left) => self.move_days(-1),
+            Event::Char('l') | Event::Key(Key::Right) => self.move_days(1),
+            Event::Char('k') | Event::Key(Key::Up) => self.move_days(-7),
+            Event::Char('j') | Event::Key(Key::Down) => self.move_days(7),
+            Event::Char('H') | Event::Key(Key::PageUp) => self.move_months(-1),
+            Event::Char('L') | Event::Key(Key::PageDown) => self.move_months(1),
+            Event::Key(Key::Enter) => self.submit(),
+            _ => EventResult::Ignored
+        }
+    }
+}
+
+/// How each day of the month containing `date` should be highlighted
+fn month_marks(date: NaiveDate) -> HashMap<NaiveDate, DayMark> {
+    let mut marks = HashMap::new();
+    for (day, entry) in month_entries(date) {
+        if entry.state.is_open() {
+            marks.insert(day, DayMark::OpenTasks);
+        } else {
+            marks.entry(day).or_insert(DayMark::Entries);
+        }
+    }
+    marks
+}
+
+/// Pop up a calendar starting at `date`. Picking a day replaces the on-screen page with that
+/// day's journal.
+pub fn calendar_dialog(siv: &mut Cursive, date: NaiveDate) {
+    let calendar = CalendarView::new(date).on_submit(|s, day| {
+        // Both the calendar and the page it was opened from
+        s.pop_layer();
+        s.pop_layer();
+        s.add_layer(daily_view(day));
+    });
+    siv.screen_mut().add_layer(
+        Dialog::around(LinearLayout::vertical()
+                       .child(calendar)
+                       .child(TextView::new("bold: entries\nunderlined: open tasks")))
+            .title("Go to date")
+            .dismiss_button("Cancel"));
+}
@@ -0,0 +1,236 @@
+use history;
+use page::{valid_collection_name, Page};
+use storage::{self, StorageError};
+use tz;
+use views::daily::daily_view;
+use views::entries::{
+    entry_list,
+    new_entry_dialog,
+    redo_change,
+    saved_collections,
+    select_nav,
+    undo_change};
+use views::query::query_dialog;
+use views::report_later;
+use views::search::search_dialog;
+
+use std::boxed::Box;
+use std::io;
+use std::rc::Rc;
+
+use cursive::Cursive;
+use cursive::view::View;
+use cursive::align::Align;
+use cursive::traits::*;
+use cursive::event::{Event, Key};
+use cursive::view::{Offset, Position};
+use cursive::theme::Effect;
+use cursive::utils::span::SpannedString;
+use cursive::views::{
+    ViewRef,
+    Dialog,
+    TextView,
+    EditView,
+    LinearLayout,
+    ListView,
+    SelectView,
+    OnEventView};
+
+type CollectionView = SelectView<String>;
+
+/// The collection index: every custom collection, which can be opened, created, renamed and deleted
+pub fn collection_index_view() -> Box<dyn View> {
+    let title = TextView::new(SpannedString::styled("Collections", Effect::Bold))
+        .align(Align::center());
+    let mut select = CollectionView::new().on_submit(|s, name: &String| {
+        s.pop_layer();
+        s.add_layer(collection_view(name));
+    });
+    match saved_collections() {
+        Ok(names) => {
+            for name in names {
+                select.add_item(name.clone(), name);
+            }
+        }
+        Err(e) => report_later(format!("Unable to list the collections: {}", e))
+    }
+
+    let index_view = OnEventView::new(LinearLayout::vertical()
+                                      .child(title)
+                                      .child(select_nav(select).with_id("collections")))
+        .on_pre_event('n', |s| name_dialog(s, "New collection", "", |s2, name| {
+            if let Err(e) = create_collection(name) {
+                s2.add_layer(Dialog::info(format!("Unable to create '{}': {}", name, e)));
+                return;
+            }
+            s2.pop_layer();
+            s2.add_layer(collection_view(name));
+        }))
+        .on_pre_event('r', rename_collection)
+        .on_pre_event(Key::Backspace, delete_collection)
+        .on_pre_event('u', undo_change)
+        .on_pre_event(Event::CtrlChar('r'), redo_change)
+        .on_pre_event('/', search_dialog)
+        .on_pre_event(':', query_dialog)
+        .on_pre_event(Key::Esc, |s| {
+            s.pop_layer();
+            s.add_layer(daily_view(tz::home().today()));
+        })
+        .on_pre_event(Event::Char('?'), |s| {
+            let help_view = ListView::new()
+                .child("n", TextView::new("Create a new collection"))
+                .child("j", TextView::new("Move the selection cursor down"))
+                .child("k", TextView::new("Move the selection cursor up"))
+                .child("enter", TextView::new("Open the selected collection"))
+                .child("r", TextView::new("Rename the selected collection"))
+                .child("backspace", TextView::new("Delete the selected collection"))
+                .child("u", TextView::new("Undo the last change"))
+                .child("ctrl-r", TextView::new("Redo the last change you undid"))
+                .child("esc", TextView::new("Return to today's journal"))
+                .child("/", TextView::new("Search every page of the journal"))
+                .child(":", TextView::new("Filter every page with a query like state:open tag:work"))
+                .child("q", TextView::new("Quit"));
+            s.screen_mut().add_layer(Dialog::around(help_view).dismiss_button("Ok"));
+        });
+    Box::new(LinearLayout::horizontal()
+             .child(index_view.min_width(40))
+             .child(Dialog::around(TextView::new("Press ? for help")).title("Bullet Terminal")))
+}
+
+/// A single collection's page. Its entries use the same keys as the daily journal.
+pub fn collection_view(name: &str) -> Box<dyn View> {
+    let page = Page::Collection(name.to_string());
+    let title = TextView::new(SpannedString::styled(page.to_string(), Effect::Bold))
+        .align(Align::center());
+
+    let collection_view = OnEventView::new(LinearLayout::vertical()
+                                           .child(title)
+                                           .child(entry_list(&page)))
+        .on_pre_event('/', search_dialog)
+        .on_pre_event(':', query_dialog)
+        .on_pre_event(Key::Esc, |s| {
+            s.pop_layer();
+            s.add_layer(collection_index_view());
+        })
+        .on_pre_event(Event::Char('?'), |s| {
+            let help_view = ListView::new()
+                .child("n", TextView::new("Add a new entry"))
+                .child("esc", TextView::new("Return to the list of collections"))
+                .child("/", TextView::new("Search every page of the journal"))
+                .child(":", TextView::new("Filter every page with a query like state:open tag:work"))
+                .child("q", TextView::new("Quit"));
+            s.screen_mut().add_layer(Dialog::around(help_view)
+                                     .title("Entries use the same keys as the daily journal")
+                                     .dismiss_button("Ok"));
+        })
+        .on_pre_event(Event::Char('n'), move |s| new_entry_dialog(s, &page));
+    Box::new(LinearLayout::horizontal()
+             .child(collection_view.min_width(40))
+             .child(Dialog::around(TextView::new("Press ? for help")).title("Bullet Terminal")))
+}
+
+/// Ask for a collection name. `cb` is only called with valid names.
+fn name_dialog<F>(siv: &mut Cursive, title: &str, content: &str, cb: F)
+where F: Fn(&mut Cursive, &str) + 'static {
+    let cb = Rc::new(cb);
+    let submit_cb = cb.clone();
+    siv.screen_mut().add_layer_at(
+        Position::new(Offset::Center, Offset::Parent(5)),
+        Dialog::around(EditView::new()
+                       .content(content)
+                       .on_submit(move |s, name| {
+                           s.pop_layer();
+                           checked_name(s, name, &*submit_cb);
+                       })
+                       .with_id("collection-name")
+                       .min_width(20))
+            .title(title)
+            .button("Ok", move |s| {
+                let name_view: ViewRef<EditView> = s.find_id("collection-name").expect("Unable to get collection-name view");
+                let name = name_view.get_content();
+                s.pop_layer();
+                checked_name(s, &name, &*cb);
+            }).dismiss_button("Cancel"));
+}
+
+fn checked_name(siv: &mut Cursive, name: &str, cb: &dyn Fn(&mut Cursive, &str)) {
+    if valid_collection_name(name) {
+        cb(siv, name.trim());
+    } else {
+        siv.add_layer(Dialog::info(format!("'{}' can't be used as a collection name", name)));
+    }
+}
+
+/// The name of the collection selected in the index, if any
+fn selected_collection(siv: &mut Cursive) -> Option<String> {
+    let collections: ViewRef<OnEventView<CollectionView>> = siv.find_id("collections").expect("Unable to get collections view");
+    let selected = collections.get_inner().selection();
+    selected.map(|name| (*name).clone())
+}
+
+fn rename_collection(siv: &mut Cursive) {
+    let old = match selected_collection(siv) {
+        Some(name) => name,
+        None => return
+    };
+    let content = old.clone();
+    name_dialog(siv, "Rename collection", &content, move |s, new| {
+        if let Err(e) = move_collection(&old, new) {
+            s.add_layer(Dialog::info(format!("Unable to rename '{}': {}", old, e)));
+            return;
+        }
+        s.pop_layer();
+        s.add_layer(collection_index_view());
+    });
+}
+
+fn delete_collection(siv: &mut Cursive) {
+    let name = match selected_collection(siv) {
+        Some(name) => name,
+        None => return
+    };
+    siv.screen_mut().add_layer_at(
+        Position::new(Offset::Center, Offset::Parent(5)),
+        Dialog::around(TextView::new(format!("Are you sure you want to delete '{}' and all of its entries?", name)))
+            .button("Yes", move |s| {
+                s.pop_layer();
+                if let Err(e) = remove_collection(&name) {
+                    s.add_layer(Dialog::info(format!("Unable to delete '{}': {}", name, e)));
+                    return;
+                }
+                s.pop_layer();
+                s.add_layer(collection_index_view());
+            }).dismiss_button("No"));
+}
+
+/// Save an empty collection, so it shows up in the index before anything is added to it
+fn create_collection(name: &str) -> Result<(), StorageError> {
+    let storage = storage::current();
+    let page = Page::Collection(name.to_string());
+    if storage.list()?.contains(&page) {
+        return Ok(());
+    }
+    storage.save(&page, &[])
+}
+
+/// Move every entry of a collection to a new name. Refuses to overwrite an existing collection.
+fn move_collection(old: &str, new: &str) -> Result<(), StorageError> {
+    let storage = storage::current();
+    let from = Page::Collection(old.to_string());
+    let to = Page::Collection(new.to_string());
+    if from == to {
+        return Ok(());
+    }
+    if storage.exists(&to)? {
+        return Err(StorageError::Io(io::Error::new(io::ErrorKind::AlreadyExists, format!("'{}' already exists", new))));
+    }
+    let entries = storage.load(&from)?;
+    history::batch(|| {
+        history::save(&to, &entries)?;
+        history::delete(&from)
+    })
+}
+
+fn remove_collection(name: &str) -> Result<(), StorageError> {
+    history::delete(&Page::Collection(name.to_string()))
+}
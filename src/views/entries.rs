@@ -0,0 +1,796 @@
+use entry::{EntryState, Entry, Signifier};
+use history;
+use page::{valid_collection_name, Page};
+use storage::{self, StorageError};
+use tz;
+use views::collections::collection_index_view;
+use views::page_view;
+use views::tags::{highlighted, show_highlight};
+use views::report_later;
+
+use std::collections::{HashMap, HashSet};
+use std::slice;
+
+use chrono::NaiveDate;
+use uuid::Uuid;
+use cursive::Cursive;
+use cursive::traits::*;
+use cursive::event::{Event, EventResult, Key};
+use cursive::view::{Offset, Position};
+use cursive::utils::span::SpannedString;
+use cursive::views::{
+    ViewRef,
+    Dialog,
+    TextView,
+    EditView,
+    LinearLayout,
+    SelectView,
+    OnEventView};
+
+pub type EntryView = SelectView<Entry>;
+
+/// Build the editable list of entries for a page. Every page with entries uses this so they
+/// all share the same editing keys.
+///
+/// The selected entry is repeated underneath with its tags and contexts highlighted, as list
+/// labels can't be styled.
+///
+/// Only one entry list may be on screen at a time, as they're all found through the "entries" id.
+pub fn entry_list(page: &Page) -> LinearLayout {
+    let submit_page = page.clone();
+    let mut select = EntryView::new()
+        .on_submit(move |s, entry| edit_entry(s, &submit_page, entry))
+        .on_select(show_highlight);
+    match storage::current().load(page) {
+        Ok(entries) => {
+            let destinations = destinations(&entries);
+            for entry in entries.into_iter() {
+                select.add_item(labelled(&entry, &destinations), entry);
+            }
+        }
+        // Saving loads the page first, so the empty list can't overwrite it
+        Err(e) => report_later(format!("Unable to read {}: {}", page, e))
+    };
+    let highlight = TextView::new(match select.selection() {
+        Some(ref entry) if !entry.tags.is_empty() || !entry.contexts.is_empty() => highlighted(&entry.content),
+        _ => SpannedString::new()
+    });
+    let list = select_nav(select)
+        .on_pre_event(Key::Backspace, bind(page, delete_entry))
+        .on_pre_event('e', bind(page, mark_event))
+        .on_pre_event('t', bind(page, mark_incomplete))
+        .on_pre_event('r', bind(page, mark_note))
+        .on_pre_event('d', bind(page, mark_done))
+        .on_pre_event(' ', bind(page, toggle_completion))
+        .on_pre_event('X', bind(page, toggle_cancelled))
+        .on_pre_event('<', bind(page, schedule_entry))
+        .on_pre_event('>', bind(page, migrate_entry))
+        .on_pre_event('o', bind(page, show_origin))
+        .on_pre_event('D', bind(page, complete_subtree))
+        .on_pre_event('z', bind(page, toggle_fold))
+        .on_pre_event('*', bind(page, toggle_priority))
+        .on_pre_event('!', bind(page, toggle_inspiration))
+        .on_pre_event('E', bind(page, toggle_explore))
+        .on_pre_event(']', bind(page, indent_entry))
+        .on_pre_event('[', bind(page, outdent_entry))
+        .on_pre_event('K', bind(page, move_up))
+        .on_pre_event('J', bind(page, move_down))
+        .on_pre_event('T', bind(page, move_to_top))
+        .on_pre_event('B', bind(page, move_to_bottom))
+        .on_pre_event('i', bind(page, insert_above))
+        .on_pre_event('a', bind(page, insert_below))
+        .on_pre_event('u', undo_change)
+        .on_pre_event(Event::CtrlChar('r'), redo_change)
+        .with_id("entries");
+    LinearLayout::vertical()
+        .child(list)
+        .child(highlight.with_id("entry-highlight"))
+}
+
+/// Wrap a select view so j and k move the selection like everywhere else
+pub fn select_nav<T: 'static>(select: SelectView<T>) -> OnEventView<SelectView<T>> {
+    // Override j, k for nav
+    OnEventView::new(select)
+        .on_pre_event_inner('j', |s| Some(EventResult::Consumed(Some(s.select_down(1)))))
+        .on_pre_event_inner('k', |s| Some(EventResult::Consumed(Some(s.select_up(1)))))
+}
+
+/// Bind a page-aware callback to a page so it can be used as a cursive callback
+fn bind(page: &Page, cb: fn(&mut Cursive, &Page)) -> impl Fn(&mut Cursive) {
+    let page = page.clone();
+    move |s| cb(s, &page)
+}
+
+/// Where `add_item` puts a new entry
+#[derive(Clone, Copy)]
+enum Placement {
+    /// After every other entry
+    End,
+    /// Right above the selected entry, at the same depth
+    Above,
+    /// After the selected entry and everything nested under it, at the same depth
+    Below,
+}
+
+/// Pop up a dialog asking for the content of a new entry, which will be added to the page
+pub fn new_entry_dialog(siv: &mut Cursive, page: &Page) {
+    entry_dialog(siv, page, Placement::End);
+}
+
+/// Add a new entry above the selected one
+fn insert_above(siv: &mut Cursive, page: &Page) {
+    entry_dialog(siv, page, Placement::Above);
+}
+
+/// Add a new entry below the selected one
+fn insert_below(siv: &mut Cursive, page: &Page) {
+    entry_dialog(siv, page, Placement::Below);
+}
+
+fn entry_dialog(siv: &mut Cursive, page: &Page, placement: Placement) {
+    let submit_page = page.clone();
+    let button_page = page.clone();
+    siv.screen_mut().add_layer(
+        Dialog::around(EditView::new()
+                       .on_submit(move |s2, entry| {
+                           add_item(s2, &submit_page, entry, placement);
+                           s2.pop_layer();
+                       }).with_id("new-entry").min_width(20))
+            .dismiss_button("Cancel")
+            .button("Add", move |s2| {
+                let edit_view: ViewRef<EditView> = s2.find_id("new-entry").expect("unable to get new-entry view");
+                add_item(s2, &button_page, edit_view.get_content().as_ref(), placement);
+                s2.pop_layer();
+            }));
+}
+
+/// Save the entries of the on-screen entry list
+fn save_page(siv: &mut Cursive, page: &Page) {
+    let (entries, selected): (Vec<Entry>, Option<Entry>) = {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        // Collapsed children aren't in the view, but they're still part of the page
+        (entry_view.iter().flat_map(|(_, entry)| entry.clone().unfold()).collect(),
+         entry_view.selection().map(|entry| (*entry).clone()))
+    };
+    let result = history::save(page, &entries);
+    if let Some(entry) = selected {
+        show_highlight(siv, &entry);
+    }
+    report(siv, result);
+}
+
+/// Add an entry to the end of a page that isn't on screen
+fn append_entry(page: &Page, entry: Entry) -> Result<(), StorageError> {
+    let mut entries = storage::current().load(page)?;
+    entries.push(entry);
+    history::save(page, &entries)
+}
+
+/// Let the user know when the journal couldn't be read or written
+pub fn report(siv: &mut Cursive, result: Result<(), StorageError>) {
+    if let Err(e) = result {
+        siv.add_layer(Dialog::info(format!("Unable to update the journal: {}", e)));
+    }
+}
+
+/// The names of every saved collection, sorted alphabetically
+pub fn saved_collections() -> Result<Vec<String>, StorageError> {
+    Ok(storage::current().list()?
+       .into_iter()
+       .filter_map(|page| match page {
+           Page::Collection(name) => Some(name),
+           _ => None
+       })
+       .collect())
+}
+
+/// How an entry is shown in a list: its signifiers in a gutter, then the entry indented under its
+/// parent, with a count of the children hidden by collapsing it
+fn label(entry: &Entry) -> String {
+    labelled(entry, &destinations(slice::from_ref(entry)))
+}
+
+/// The entry's label, with the page it was migrated (>) to when that's among `destinations`
+fn labelled(entry: &Entry, destinations: &HashMap<Uuid, Page>) -> String {
+    let indent = "  ".repeat(entry.depth);
+    let hidden: usize = entry.folded.iter().map(|child| child.clone().unfold().len()).sum();
+    let mut label = format!("{} {}{}", entry.gutter(), indent, entry.to_display());
+    if let Some(page) = destinations.get(&entry.id) {
+        label.push_str(&format!(" (→ {})", page));
+    }
+    if hidden > 0 {
+        label.push_str(&format!(" (+{})", hidden));
+    }
+    label
+}
+
+/// The pages the migrated (>) entries among `entries` went to, by their ids. Found from the copies
+/// left there, which point back with their origin. Only looked up when something was migrated,
+/// as it reads the whole journal; when it can't be read the destinations just aren't shown.
+fn destinations(entries: &[Entry]) -> HashMap<Uuid, Page> {
+    let migrated: HashSet<Uuid> = entries.iter()
+        .filter(|entry| matches!(entry.state, EntryState::Collected(_)))
+        .map(|entry| entry.id)
+        .collect();
+    if migrated.is_empty() {
+        return HashMap::new();
+    }
+    storage::current().entries().unwrap_or_default().into_iter()
+        .filter_map(|(page, copy)| copy.origin.filter(|origin| migrated.contains(origin)).map(|origin| (origin, page)))
+        .collect()
+}
+
+/// The index just past the last descendant of the entry at `idx`
+fn subtree_end(entry_view: &EntryView, idx: usize) -> usize {
+    let depth = entry_view.get_item(idx).expect("The entry exists").1.depth;
+    (idx + 1..entry_view.len())
+        .find(|&i| entry_view.get_item(i).expect("Within the list").1.depth <= depth)
+        .unwrap_or_else(|| entry_view.len())
+}
+
+fn replace_entry(idx: usize, replacement: Entry, entry_view: &mut EntryView) {
+    let _ = entry_view.remove_item(idx);
+    entry_view.insert_item(idx, label(&replacement), replacement);
+    let _ = entry_view.set_selection(idx);
+}
+
+/// Change the state of the currently-selected entry
+fn mark(siv: &mut Cursive, page: &Page, state: EntryState) {
+    {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let mut entry = (*entry_view.selection().unwrap()).clone();
+        entry.state = state;
+        replace_entry(idx, entry, entry_view);
+    }
+    save_page(siv, page);
+}
+
+/// Change the currently-selected entry to an event (o)
+fn mark_event(siv: &mut Cursive, page: &Page) {
+    mark(siv, page, EntryState::Event);
+}
+
+/// Change the currently-selected entry to a note (-)
+fn mark_note(siv: &mut Cursive, page: &Page) {
+    mark(siv, page, EntryState::Note);
+}
+
+/// Change the currently-selected entry to a task (•)
+fn mark_incomplete(siv: &mut Cursive, page: &Page) {
+    mark(siv, page, EntryState::Incomplete);
+}
+
+fn mark_done(siv: &mut Cursive, page: &Page) {
+    mark(siv, page, EntryState::Completed);
+}
+
+/// Toggle the completion state of a task (• or ×). If a non-task is selected,
+/// nothing will happen.
+fn toggle_completion(siv: &mut Cursive, page: &Page) {
+    {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let (text, entry) = entry_view.get_item_mut(idx).unwrap();
+        entry.toggle_state();
+        text.replace_range(0.., &label(entry));
+    }
+    save_page(siv, page);
+}
+
+/// Cancel the selected task because it's no longer relevant, or reopen it if it was cancelled.
+/// Other entries are left alone.
+fn toggle_cancelled(siv: &mut Cursive, page: &Page) {
+    {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let (text, entry) = entry_view.get_item_mut(idx).unwrap();
+        if !entry.toggle_cancelled() {
+            return;
+        }
+        text.replace_range(0.., &label(entry));
+    }
+    save_page(siv, page);
+}
+
+/// Add or remove a signifier on the selected entry
+fn toggle_signifier(siv: &mut Cursive, page: &Page, signifier: Signifier) {
+    {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let (text, entry) = entry_view.get_item_mut(idx).unwrap();
+        entry.toggle_signifier(signifier);
+        text.replace_range(0.., &label(entry));
+    }
+    save_page(siv, page);
+}
+
+/// Toggle the priority (*) signifier of the selected entry
+fn toggle_priority(siv: &mut Cursive, page: &Page) {
+    toggle_signifier(siv, page, Signifier::Priority);
+}
+
+/// Toggle the inspiration (!) signifier of the selected entry
+fn toggle_inspiration(siv: &mut Cursive, page: &Page) {
+    toggle_signifier(siv, page, Signifier::Inspiration);
+}
+
+/// Toggle the explore (?) signifier of the selected entry
+fn toggle_explore(siv: &mut Cursive, page: &Page) {
+    toggle_signifier(siv, page, Signifier::Explore);
+}
+
+/// Mark the selected task done (×) along with every open task nested under it. Notes and events
+/// are left as they are.
+fn complete_subtree(siv: &mut Cursive, page: &Page) {
+    fn complete(entry: &mut Entry) {
+        if let EntryState::Incomplete = entry.state {
+            entry.state = EntryState::Completed;
+        }
+        entry.folded.iter_mut().for_each(complete);
+    }
+    {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        for i in idx..subtree_end(entry_view, idx) {
+            let (text, entry) = entry_view.get_item_mut(i).unwrap();
+            complete(entry);
+            text.replace_range(0.., &label(entry));
+        }
+    }
+    save_page(siv, page);
+}
+
+/// Collapse the children of the selected entry, or show them again if they're collapsed
+fn toggle_fold(siv: &mut Cursive, page: &Page) {
+    {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let mut entry = (*entry_view.selection().unwrap()).clone();
+        if entry.folded.is_empty() {
+            let end = subtree_end(entry_view, idx);
+            for i in (idx + 1..end).rev() {
+                entry.folded.insert(0, (*entry_view.get_item(i).unwrap().1).clone());
+                let _ = entry_view.remove_item(i);
+            }
+            replace_entry(idx, entry, entry_view);
+        } else {
+            for (offset, child) in entry.folded.drain(..).enumerate() {
+                entry_view.insert_item(idx + 1 + offset, label(&child), child);
+            }
+            replace_entry(idx, entry, entry_view);
+        }
+    }
+    // Folding only changes what's on screen, but the page is saved so nothing can be lost
+    save_page(siv, page);
+}
+
+/// Nest the selected entry and its children one level deeper, under the entry above it
+fn indent_entry(siv: &mut Cursive, page: &Page) {
+    nest_selected(siv, page, 1);
+}
+
+/// Move the selected entry and its children one level out
+fn outdent_entry(siv: &mut Cursive, page: &Page) {
+    nest_selected(siv, page, -1);
+}
+
+fn nest_selected(siv: &mut Cursive, page: &Page, levels: isize) {
+    {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let depth = entry_view.selection().unwrap().depth;
+        let allowed = if levels > 0 {
+            // An entry can only be nested one level deeper than the one above it
+            idx > 0 && entry_view.get_item(idx - 1).unwrap().1.depth >= depth
+        } else {
+            depth > 0
+        };
+        if !allowed {
+            return;
+        }
+        for i in idx..subtree_end(entry_view, idx) {
+            let (text, entry) = entry_view.get_item_mut(i).unwrap();
+            entry.nest(levels);
+            text.replace_range(0.., &label(entry));
+        }
+    }
+    save_page(siv, page);
+}
+
+/// Move the selected entry up among its siblings
+fn move_up(siv: &mut Cursive, page: &Page) {
+    move_selected(siv, page, -1);
+}
+
+/// Move the selected entry down among its siblings
+fn move_down(siv: &mut Cursive, page: &Page) {
+    move_selected(siv, page, 1);
+}
+
+/// Move the selected entry above all of its siblings
+fn move_to_top(siv: &mut Cursive, page: &Page) {
+    move_selected(siv, page, isize::MIN);
+}
+
+/// Move the selected entry below all of its siblings
+fn move_to_bottom(siv: &mut Cursive, page: &Page) {
+    move_selected(siv, page, isize::MAX);
+}
+
+/// Move the selected entry and everything nested under it `steps` places down among the entries
+/// nested under the same parent, or up when negative. It stops at the first or last of them, as
+/// moving it out of its parent is left to `[`.
+fn move_selected(siv: &mut Cursive, page: &Page, steps: isize) {
+    let selected = {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let depth_at = |i: usize| entry_view.get_item(i).expect("Within the list").1.depth;
+        let depth = depth_at(idx);
+        let first = (0..idx).rev().find(|&i| depth_at(i) < depth).map_or(0, |parent| parent + 1);
+        let end = (idx + 1..entry_view.len()).find(|&i| depth_at(i) < depth).unwrap_or_else(|| entry_view.len());
+        // Where each sibling's subtree starts, followed by where the last one ends
+        let mut starts: Vec<usize> = (first..end).filter(|&i| i == first || depth_at(i) == depth).collect();
+        let block = starts.iter().position(|&start| start == idx).expect("The entry starts its subtree");
+        let target = (block as isize).saturating_add(steps).clamp(0, starts.len() as isize - 1) as usize;
+        if target == block {
+            return;
+        }
+        starts.push(end);
+        let size = starts[block + 1] - idx;
+        let mut subtree = Vec::new();
+        for _ in 0..size {
+            subtree.push((*entry_view.get_item(idx).unwrap().1).clone());
+            let _ = entry_view.remove_item(idx);
+        }
+        // Where the sibling it's swapping places with starts or ends, now the subtree is out
+        let at = if target < block { starts[target] } else { starts[target + 1] - size };
+        for (offset, entry) in subtree.into_iter().enumerate() {
+            entry_view.insert_item(at + offset, label(&entry), entry);
+        }
+        entry_view.set_selection(at)
+    };
+    selected(siv);
+    save_page(siv, page);
+}
+
+/// Ask for a date to schedule (<) the selected task for. Only tasks can be scheduled.
+fn schedule_entry(siv: &mut Cursive, page: &Page) {
+    {
+        let event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        match event_view.get_inner().selection() {
+            Some(ref entry) if entry.state.is_open() => (),
+            _ => return
+        }
+    }
+    let tomorrow = tz::home().today().succ_opt().expect("We aren't anywhere near the end of time");
+    let submit_page = page.clone();
+    let button_page = page.clone();
+    siv.screen_mut().add_layer_at(
+        Position::new(Offset::Center, Offset::Parent(5)),
+        Dialog::around(EditView::new()
+                       .content(tomorrow.format("%Y-%m-%d").to_string())
+                       .on_submit(move |s, text| {
+                           s.pop_layer();
+                           schedule_selected(s, &submit_page, text);
+                       })
+                       .with_id("schedule-date")
+                       .min_width(12))
+            .title("Schedule for (YYYY-MM-DD)")
+            .button("Schedule", move |s| {
+                let date_view: ViewRef<EditView> = s.find_id("schedule-date").expect("Unable to get schedule-date view");
+                let text = date_view.get_content();
+                s.pop_layer();
+                schedule_selected(s, &button_page, &text);
+            }).dismiss_button("Cancel"));
+}
+
+/// Mark the selected task as scheduled (<) for the given day and add a fresh copy of it to that
+/// day's page, so it shows up when the day arrives.
+fn schedule_selected(siv: &mut Cursive, page: &Page, text: &str) {
+    let target = match NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d") {
+        Ok(date) => date,
+        Err(_) => {
+            siv.add_layer(Dialog::info(format!("'{}' isn't a date. Dates look like 2018-06-30", text.trim())));
+            return;
+        }
+    };
+    let target_page = Page::Day(target);
+    let copy = {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let mut entry = (*entry_view.selection().unwrap()).clone();
+        let copy = entry.copy(EntryState::Incomplete);
+        entry.state = EntryState::Scheduled(tz::anchor(target));
+        replace_entry(idx, entry, entry_view);
+        if target_page == *page {
+            // Scheduling for the page we're on; the copy has to go through the view or it'd be
+            // overwritten when the page is saved
+            entry_view.add_item(label(&copy), copy);
+            None
+        } else {
+            Some(copy)
+        }
+    };
+    history::batch(|| {
+        save_page(siv, page);
+        if let Some(copy) = copy {
+            report(siv, append_entry(&target_page, copy));
+        }
+    });
+}
+
+/// Ask which collection to migrate (>) the selected task to, offering to create a new one.
+/// Only tasks can be migrated.
+fn migrate_entry(siv: &mut Cursive, page: &Page) {
+    {
+        let event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        match event_view.get_inner().selection() {
+            Some(ref entry) if entry.state.is_open() => (),
+            _ => return
+        }
+    }
+    let select_page = page.clone();
+    let submit_page = page.clone();
+    let button_page = page.clone();
+    let mut collections = SelectView::new().on_submit(move |s, name: &String| {
+        s.pop_layer();
+        migrate_selected(s, &select_page, name);
+    });
+    let names = match saved_collections() {
+        Ok(names) => names,
+        Err(e) => return report(siv, Err(e))
+    };
+    for name in names {
+        collections.add_item(name.clone(), name);
+    }
+    siv.screen_mut().add_layer_at(
+        Position::new(Offset::Center, Offset::Parent(5)),
+        Dialog::around(LinearLayout::vertical()
+                       .child(select_nav(collections))
+                       .child(TextView::new("New collection:"))
+                       .child(EditView::new()
+                              .on_submit(move |s, name| {
+                                  s.pop_layer();
+                                  migrate_selected(s, &submit_page, name);
+                              })
+                              .with_id("new-collection")
+                              .min_width(20)))
+            .title("Migrate to collection")
+            .button("Create", move |s| {
+                let name_view: ViewRef<EditView> = s.find_id("new-collection").expect("Unable to get new-collection view");
+                let name = name_view.get_content();
+                s.pop_layer();
+                migrate_selected(s, &button_page, &name);
+            }).dismiss_button("Cancel"));
+}
+
+/// Move the selected task to the end of a collection, creating it if needed. A migrated (>) stub
+/// noting where it went is left behind in its place.
+fn migrate_selected(siv: &mut Cursive, page: &Page, name: &str) {
+    if !valid_collection_name(name) {
+        siv.add_layer(Dialog::info(format!("'{}' can't be used as a collection name", name)));
+        return;
+    }
+    let target_page = Page::Collection(name.trim().to_string());
+    if target_page == *page {
+        return;
+    }
+    let moved = {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let idx = match entry_view.selected_id() {
+            Some(idx) => idx,
+            None => return
+        };
+        let mut stub = (*entry_view.selection().unwrap()).clone();
+        let moved = stub.copy(EntryState::Incomplete);
+        stub.state = EntryState::Collected(tz::home().now());
+        replace_entry(idx, stub, entry_view);
+        moved
+    };
+    history::batch(|| {
+        save_page(siv, page);
+        report(siv, append_entry(&target_page, moved));
+    });
+    // Now the copy's been saved, the stub can show where it went
+    let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+    let entry_view: &mut EntryView = event_view.get_inner_mut();
+    if let Some(idx) = entry_view.selected_id() {
+        let (text, entry) = entry_view.get_item_mut(idx).unwrap();
+        text.replace_range(0.., &label(entry));
+    }
+}
+
+/// Go to the page the selected entry was scheduled or migrated from
+fn show_origin(siv: &mut Cursive, _page: &Page) {
+    let origin = {
+        let event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        match event_view.get_inner().selection().and_then(|entry| entry.origin) {
+            Some(origin) => origin,
+            None => return
+        }
+    };
+    match storage::current().find(origin) {
+        Ok(Some((page, _))) => open_entry(siv, &page, origin),
+        Ok(None) => siv.add_layer(Dialog::info("The entry this came from has been deleted")),
+        Err(e) => report(siv, Err(e))
+    }
+}
+
+/// Replace the on-screen page with `page`, selecting the entry with the given id
+pub fn open_entry(siv: &mut Cursive, page: &Page, id: Uuid) {
+    siv.pop_layer();
+    siv.add_layer(page_view(page, tz::home().today()));
+    let selected = {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        let found = entry_view.iter().position(|(_, entry)| entry.id == id);
+        found.map(|idx| entry_view.set_selection(idx))
+    };
+    if let Some(cb) = selected {
+        cb(siv);
+    }
+}
+
+/// Revert the last change to the journal and show the page it was made on
+pub fn undo_change(siv: &mut Cursive) {
+    match history::undo() {
+        Ok(Some((page, idx))) => open_position(siv, &page, idx),
+        Ok(None) => siv.add_layer(Dialog::info("Nothing to undo")),
+        Err(e) => report(siv, Err(e))
+    }
+}
+
+/// Reapply the last undone change and show the page it was made on
+pub fn redo_change(siv: &mut Cursive) {
+    match history::redo() {
+        Ok(Some((page, idx))) => open_position(siv, &page, idx),
+        Ok(None) => siv.add_layer(Dialog::info("Nothing to redo")),
+        Err(e) => report(siv, Err(e))
+    }
+}
+
+/// Replace the on-screen page with `page`, selecting the entry at `idx` or the last one before it.
+/// A collection that's gone, after undoing its creation or redoing its deletion, shows the index.
+fn open_position(siv: &mut Cursive, page: &Page, idx: usize) {
+    siv.pop_layer();
+    if let Page::Collection(_) = *page {
+        if !storage::current().exists(page).unwrap_or(true) {
+            siv.add_layer(collection_index_view());
+            return;
+        }
+    }
+    siv.add_layer(page_view(page, tz::home().today()));
+    let selected = {
+        let mut event_view: ViewRef<OnEventView<EntryView>> = siv.find_id("entries").expect("Unable to get entry view");
+        let entry_view: &mut EntryView = event_view.get_inner_mut();
+        match entry_view.len() {
+            0 => None,
+            len => Some(entry_view.set_selection(idx.min(len - 1)))
+        }
+    };
+    if let Some(cb) = selected {
+        cb(siv);
+    }
+}
+
+fn delete_entry(siv: &mut Cursive, page: &Page) {
+    let page = page.clone();
+    siv.screen_mut().add_layer_at(
+        Position::new(Offset::Center, Offset::Parent(5)),
+        Dialog::around(TextView::new("Are you sure you want to delete the selected entry and everything nested under it?"))
+            .button("Yes", move |s| {
+                {
+                    let mut event_view: ViewRef<OnEventView<EntryView>> = s.find_id("entries").expect("Unable to get entry view");
+                    let entry_view: &mut EntryView = event_view.get_inner_mut();
+                    if let Some(selected) = entry_view.selected_id() {
+                        for idx in (selected..subtree_end(entry_view, selected)).rev() {
+                            let cb = entry_view.remove_item(idx);
+                            cb(s);
+                        }
+                    }
+                }
+                save_page(s, &page);
+                s.pop_layer();
+            }).dismiss_button("No"));
+}
+
+fn update_at_index(entry_view: &mut EntryView, idx: usize, new_content: &str) {
+    let (text, entry) = entry_view.get_item_mut(idx).unwrap();
+    entry.set_content(new_content);
+    text.replace_range(0.., &label(entry));
+}
+
+/// Edit the contents of the currently-selected entry.
+fn edit_entry(siv: &mut Cursive, page: &Page, orig_entry: &Entry) {
+    let content = orig_entry.content.clone();
+    let submit_page = page.clone();
+    let button_page = page.clone();
+    siv.screen_mut().add_layer_at(
+        Position::new(Offset::Center, Offset::Parent(5)),
+        Dialog::around(EditView::new()
+                       .content(content)
+                       .on_submit(move |s, text| {
+                           {
+                               let mut event_view: ViewRef<OnEventView<EntryView>> = s.find_id("entries").expect("Unable to get entry view");
+                               let entry_view: &mut EntryView = event_view.get_inner_mut();
+                               let idx = entry_view.selected_id().unwrap();
+                               update_at_index(entry_view, idx, text);
+                           }
+                           save_page(s, &submit_page);
+                           s.pop_layer();
+                       })
+                       .with_id("update"))
+            .button("Update", move |s| {
+                {
+                    let update_view: ViewRef<EditView> = s.find_id("update").expect("Unable to get update view");
+                    let mut event_view: ViewRef<OnEventView<EntryView>> = s.find_id("entries").expect("Unable to get entry view");
+                    let entry_view: &mut EntryView = event_view.get_inner_mut();
+                    let idx = entry_view.selected_id().unwrap();
+                    update_at_index(entry_view, idx, &update_view.get_content());
+                }
+                save_page(s, &button_page);
+                s.pop_layer();
+            }).dismiss_button("Cancel"),
+    );
+}
+
+/// Add an entry. By default entrys are added as tasks (•)
+fn add_item(s: &mut Cursive, page: &Page, text: &str, placement: Placement) {
+    if !text.is_empty() {
+        {
+            let mut event_view: ViewRef<OnEventView<EntryView>> = s.find_id("entries").expect("Unable to get entry view");
+            let entry_view: &mut EntryView = event_view.get_inner_mut();
+            let (idx, depth) = match (placement, entry_view.selected_id()) {
+                (Placement::Above, Some(selected)) => (selected, entry_view.selection().unwrap().depth),
+                (Placement::Below, Some(selected)) => (subtree_end(entry_view, selected), entry_view.selection().unwrap().depth),
+                _ => (entry_view.len(), 0)
+            };
+            let mut entry = Entry::new(text, EntryState::Incomplete);
+            entry.depth = depth;
+            entry_view.insert_item(idx, label(&entry), entry);
+            let cb = entry_view.set_selection(idx);
+            cb(s);
+        }
+        s.focus_id("entries").unwrap();
+        save_page(s, page);
+    }
+}
@@ -0,0 +1,119 @@
+use entry::Entry;
+use page::{self, Page};
+use storage;
+use tz;
+use views::collections::collection_index_view;
+use views::daily::daily_view;
+use views::entries::{entry_list, new_entry_dialog, select_nav};
+use views::monthly::monthly_view;
+use views::query::query_dialog;
+use views::readable_entries;
+use views::search::search_dialog;
+use views::tags::tag_index_view;
+
+use std::boxed::Box;
+
+use chrono::NaiveDate;
+use cursive::Cursive;
+use cursive::view::View;
+use cursive::align::Align;
+use cursive::traits::*;
+use cursive::event::{Event, Key};
+use cursive::theme::Effect;
+use cursive::utils::span::SpannedString;
+use cursive::views::{
+    Dialog,
+    TextView,
+    LinearLayout,
+    ListView,
+    SelectView,
+    OnEventView};
+
+/// How many months the future log looks ahead, including the current one
+const FUTURE_MONTHS: i32 = 6;
+
+/// The future log: every month of the next few months with the tasks scheduled into it,
+/// followed by the free-floating entries of the future log itself.
+pub fn future_view(date: NaiveDate) -> Box<dyn View> {
+    let page = Page::Future;
+    let start = page::first_of_month(date);
+    let end = page::add_months(start, FUTURE_MONTHS);
+    let title = TextView::new(SpannedString::styled(page.to_string(), Effect::Bold))
+        .align(Align::center());
+
+    let mut scheduled: Vec<(NaiveDate, Entry)> = match storage::current().scheduled_between(start, end) {
+        Ok(found) => found,
+        // Go through the pages one by one, so a page that can't be read doesn't hide the rest
+        Err(_) => readable_entries(|_| true)
+    }.into_iter()
+        .filter_map(|(_, entry)| storage::scheduled_day(&entry).map(|day| (day, entry)))
+        .filter(|&(day, _)| day >= start && day < end)
+        .collect();
+    scheduled.sort_by_key(|&(day, _)| day);
+    let mut layout = LinearLayout::vertical().child(title);
+    for offset in 0..FUTURE_MONTHS {
+        let month = page::add_months(start, offset);
+        let next = page::add_months(month, 1);
+        let mut select = SelectView::new().on_submit(|s, day: &NaiveDate| {
+            s.pop_layer();
+            s.add_layer(daily_view(*day));
+        });
+        for &(day, ref entry) in scheduled.iter().filter(|&&(day, _)| day >= month && day < next) {
+            select.add_item(format!("{} {}", day.format("%d %a"), entry.to_display()), day);
+        }
+        layout.add_child(TextView::new(SpannedString::styled(month.format("%B %Y").to_string(), Effect::Bold)));
+        layout.add_child(select_nav(select));
+    }
+    layout.add_child(TextView::new(SpannedString::styled("Unscheduled", Effect::Bold)));
+    layout.add_child(entry_list(&page));
+
+    let future_view = OnEventView::new(layout)
+        .on_pre_event('l', move |s| change_start(s, start, 1))
+        .on_pre_event('h', move |s| change_start(s, start, -1))
+        .on_pre_event('m', move |s| {
+            s.pop_layer();
+            s.add_layer(monthly_view(start));
+        })
+        .on_pre_event(Key::Esc, |s| {
+            s.pop_layer();
+            s.add_layer(daily_view(tz::home().today()));
+        })
+        .on_pre_event('/', search_dialog)
+        .on_pre_event(':', query_dialog)
+        .on_pre_event('#', |s| {
+            s.pop_layer();
+            s.add_layer(tag_index_view());
+        })
+        .on_pre_event('c', |s| {
+            s.pop_layer();
+            s.add_layer(collection_index_view());
+        })
+        .on_pre_event(Event::Char('?'), |s| {
+            let help_view = ListView::new()
+                .child("n", TextView::new("Add a new entry to the future log"))
+                .child("h", TextView::new("Start the future log one month earlier"))
+                .child("l", TextView::new("Start the future log one month later"))
+                .child("tab", TextView::new("Switch between the months and the unscheduled entries"))
+                .child("enter", TextView::new("Open the day a task is scheduled for"))
+                .child("m", TextView::new("View the monthly log"))
+                .child("c", TextView::new("View your collections"))
+                .child("#", TextView::new("Browse your #tags and @contexts"))
+                .child("esc", TextView::new("Return to today's journal"))
+                .child("/", TextView::new("Search every page of the journal"))
+                .child(":", TextView::new("Filter every page with a query like state:open tag:work"))
+                .child("q", TextView::new("Quit"));
+            s.screen_mut().add_layer(Dialog::around(help_view)
+                                     .title("Entries use the same keys as the daily journal")
+                                     .dismiss_button("Ok"));
+        })
+        .on_pre_event(Event::Char('n'), move |s| new_entry_dialog(s, &page));
+    Box::new(LinearLayout::horizontal()
+             .child(future_view.min_width(40))
+             .child(Dialog::around(TextView::new("Press ? for help")).title("Bullet Terminal")))
+}
+
+/// Replace the on-screen future log with one starting `diff` months away from `start`
+fn change_start(siv: &mut Cursive, start: NaiveDate, diff: i32) {
+    siv.pop_layer();
+    siv.add_layer(future_view(page::add_months(start, diff)));
+}
@@ -1 +1,78 @@
+pub mod calendar;
+pub mod collections;
 pub mod daily;
+pub mod entries;
+pub mod future;
+pub mod monthly;
+pub mod query;
+pub mod search;
+pub mod signifiers;
+pub mod tags;
+pub mod upgrade;
+
+use entry::Entry;
+use page::Page;
+use storage;
+
+use std::boxed::Box;
+use std::cell::RefCell;
+
+use chrono::NaiveDate;
+use cursive::Cursive;
+use cursive::view::View;
+use cursive::views::Dialog;
+
+/// The view showing a page. `today` picks which months the future log starts from.
+pub fn page_view(page: &Page, today: NaiveDate) -> Box<dyn View> {
+    match page {
+        Page::Day(date) => daily::daily_view(*date),
+        Page::Month(date) => monthly::monthly_view(*date),
+        Page::Future => future::future_view(today),
+        Page::Collection(name) => collections::collection_view(name),
+    }
+}
+
+/// Shows a message once the view being built is on screen
+type Later = Box<dyn Fn(String)>;
+
+thread_local! {
+    /// For views that are still being built, and so can't add a dialog themselves
+    static LATER: RefCell<Option<Later>> = RefCell::new(None);
+}
+
+/// Let views report problems while they're being built. Must be called before any views are built.
+pub fn install_reports(siv: &Cursive) {
+    let sink = siv.cb_sink().clone();
+    LATER.with(|later| *later.borrow_mut() = Some(Box::new(move |message: String| {
+        sink.send(Box::new(move |s: &mut Cursive| s.add_layer(Dialog::info(message))));
+    })));
+}
+
+/// Let the user know part of the journal couldn't be read while building a view. The message is
+/// shown once the view is on screen.
+pub fn report_later(message: String) {
+    LATER.with(|later| match *later.borrow() {
+        Some(ref show) => show(message),
+        None => eprintln!("{}", message)
+    });
+}
+
+/// The entries of every page picked by `wanted`, for a view being built. Pages that can't be read
+/// are left out and reported.
+pub fn readable_entries<F: Fn(&Page) -> bool>(wanted: F) -> Vec<(Page, Entry)> {
+    match storage::readable_entries(&*storage::current(), wanted) {
+        Ok((found, unreadable)) => {
+            if !unreadable.is_empty() {
+                let problems: Vec<String> = unreadable.iter()
+                    .map(|(page, e)| format!("{}: {}", page, e))
+                    .collect();
+                report_later(format!("Unable to read part of the journal, which is left out:\n{}", problems.join("\n")));
+            }
+            found
+        }
+        Err(e) => {
+            report_later(format!("Unable to read the journal: {}", e));
+            Vec::new()
+        }
+    }
+}
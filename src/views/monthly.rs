@@ -0,0 +1,119 @@
+use entry::Entry;
+use page::{self, calendar_line, Page};
+use storage;
+use tz;
+use views::collections::collection_index_view;
+use views::daily::daily_view;
+use views::entries::{entry_list, new_entry_dialog, select_nav};
+use views::future::future_view;
+use views::query::query_dialog;
+use views::readable_entries;
+use views::search::search_dialog;
+use views::signifiers::signifier_filter_dialog;
+use views::tags::tag_index_view;
+
+use std::boxed::Box;
+
+use chrono::NaiveDate;
+use cursive::Cursive;
+use cursive::view::View;
+use cursive::align::Align;
+use cursive::traits::*;
+use cursive::event::{Event, Key};
+use cursive::theme::Effect;
+use cursive::utils::span::SpannedString;
+use cursive::views::{
+    Dialog,
+    TextView,
+    LinearLayout,
+    ListView,
+    SelectView,
+    OnEventView};
+
+/// The monthly log: a calendar listing every day of the month with its events, followed by
+/// the month's own task list.
+pub fn monthly_view(date: NaiveDate) -> Box<dyn View> {
+    let page = Page::month_of(date);
+    let title = TextView::new(SpannedString::styled(page.to_string(), Effect::Bold))
+        .align(Align::center());
+
+    let mut calendar = SelectView::new().on_submit(|s, day: &NaiveDate| {
+        s.pop_layer();
+        s.add_layer(daily_view(*day));
+    });
+    let month_entries = month_entries(date);
+    for day in page::days_of_month(date) {
+        calendar.add_item(calendar_line(day, &month_entries), day);
+    }
+    let month_view = OnEventView::new(LinearLayout::vertical()
+                                      .child(title)
+                                      .child(select_nav(calendar))
+                                      .child(TextView::new(SpannedString::styled("Tasks", Effect::Bold)))
+                                      .child(entry_list(&page)))
+        .on_pre_event('l', move |s| change_month(s, date, 1))
+        .on_pre_event('h', move |s| change_month(s, date, -1))
+        .on_pre_event('f', move |s| {
+            s.pop_layer();
+            s.add_layer(future_view(date));
+        })
+        .on_pre_event(Key::Esc, |s| {
+            s.pop_layer();
+            s.add_layer(daily_view(tz::home().today()));
+        })
+        .on_pre_event('F', move |s| signifier_filter_dialog(s, date))
+        .on_pre_event('/', search_dialog)
+        .on_pre_event(':', query_dialog)
+        .on_pre_event('#', |s| {
+            s.pop_layer();
+            s.add_layer(tag_index_view());
+        })
+        .on_pre_event('c', |s| {
+            s.pop_layer();
+            s.add_layer(collection_index_view());
+        })
+        .on_pre_event(Event::Char('?'), |s| {
+            let help_view = ListView::new()
+                .child("n", TextView::new("Add a new task to the month"))
+                .child("h", TextView::new("View the previous month's log"))
+                .child("l", TextView::new("View the next month's log"))
+                .child("tab", TextView::new("Switch between the calendar and the task list"))
+                .child("enter", TextView::new("Open the selected day's journal"))
+                .child("f", TextView::new("View your future log"))
+                .child("c", TextView::new("View your collections"))
+                .child("#", TextView::new("Browse your #tags and @contexts"))
+                .child("F", TextView::new("Show this month's entries with a signifier"))
+                .child("esc", TextView::new("Return to today's journal"))
+                .child("/", TextView::new("Search every page of the journal"))
+                .child(":", TextView::new("Filter every page with a query like state:open tag:work"))
+                .child("q", TextView::new("Quit"));
+            s.screen_mut().add_layer(Dialog::around(help_view)
+                                     .title("Tasks use the same keys as the daily journal")
+                                     .dismiss_button("Ok"));
+        })
+        .on_pre_event(Event::Char('n'), move |s| new_entry_dialog(s, &page));
+    Box::new(LinearLayout::horizontal()
+             .child(month_view.min_width(40))
+             .child(Dialog::around(TextView::new("Press ? for help")).title("Bullet Terminal")))
+}
+
+/// Every entry of the days in the month containing `date`, along with its day. Days that can't be
+/// read are reported and left out.
+pub fn month_entries(date: NaiveDate) -> Vec<(NaiveDate, Entry)> {
+    let (start, end) = (page::first_of_month(date), page::add_months(date, 1));
+    if let Ok(found) = storage::current().entries_between(start, end) {
+        return found;
+    }
+    // Go through the days one by one, so a day that can't be read doesn't hide the rest
+    readable_entries(|page| matches!(page, Page::Day(day) if *day >= start && *day < end)).into_iter()
+        .filter_map(|(page, entry)| match page {
+            Page::Day(day) => Some((day, entry)),
+            _ => None
+        })
+        .collect()
+}
+
+/// Replace the on-screen monthly log with the one `diff` months away from `current`
+fn change_month(siv: &mut Cursive, current: NaiveDate, diff: i32) {
+    siv.pop_layer();
+    siv.add_layer(monthly_view(page::add_months(current, diff)));
+}
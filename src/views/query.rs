@@ -0,0 +1,176 @@
+use entry::Entry;
+use history;
+use page::Page;
+use query::Query;
+use storage;
+use tz;
+use views::daily::daily_view;
+use views::entries::{open_entry, redo_change, report, select_nav, undo_change};
+use views::report_later;
+
+use std::boxed::Box;
+
+use cursive::Cursive;
+use cursive::view::View;
+use cursive::align::Align;
+use cursive::traits::*;
+use cursive::event::{Event, Key};
+use cursive::theme::Effect;
+use cursive::utils::span::SpannedString;
+use cursive::views::{
+    ViewRef,
+    Dialog,
+    EditView,
+    TextView,
+    LinearLayout,
+    ListView,
+    SelectView,
+    OnEventView};
+
+type QueryResults = SelectView<(Page, Entry)>;
+
+/// Pop up a prompt for a query like `state:open tag:work after:2024-01-01 "budget"`. Running it
+/// replaces the on-screen page with the matching entries.
+pub fn query_dialog(siv: &mut Cursive) {
+    siv.screen_mut().add_layer(
+        Dialog::around(LinearLayout::vertical()
+                       .child(EditView::new()
+                              .on_submit(run_query)
+                              .min_width(40))
+                       .child(TextView::new("state: tag: context: sig: after: before: \"text\"").with_id("query-status")))
+            .title("Query")
+            .dismiss_button("Close"));
+}
+
+/// Show the entries matching the query, or what's wrong with it
+fn run_query(siv: &mut Cursive, text: &str) {
+    match Query::parse(text) {
+        Ok(query) => {
+            // The prompt and the page it was opened from
+            siv.pop_layer();
+            siv.pop_layer();
+            siv.add_layer(query_view(text, &query));
+        }
+        Err(e) => {
+            if let Some(mut status) = siv.find_id::<TextView>("query-status") {
+                status.set_content(e.to_string());
+            }
+        }
+    }
+}
+
+fn result_label(page: &Page, entry: &Entry) -> String {
+    format!("{:<12} {}{}", page.to_string(), entry.gutter(), entry.to_display())
+}
+
+/// A virtual page of every entry matching the query, gathered from all pages. Entries are changed
+/// on their own pages straight away, but stay listed until the query is run again.
+fn query_view(text: &str, query: &Query) -> Box<dyn View> {
+    let title = TextView::new(SpannedString::styled(text.to_string(), Effect::Bold))
+        .align(Align::center());
+    let mut select = QueryResults::new().on_submit(|s, found: &(Page, Entry)| edit_result(s, &found.1));
+    let found = match query.run(&*storage::current()) {
+        Ok((found, unreadable)) => {
+            for (page, e) in unreadable {
+                report_later(format!("Unable to read {}, which is left out: {}", page, e));
+            }
+            found
+        }
+        Err(e) => {
+            report_later(format!("Unable to read the journal: {}", e));
+            Vec::new()
+        }
+    };
+    let count = found.len();
+    for (page, entry) in found {
+        select.add_item(result_label(&page, &entry), (page, entry));
+    }
+    let list = select_nav(select)
+        .on_pre_event(' ', |s| update_result(s, Entry::toggle_state))
+        .on_pre_event('X', |s| update_result(s, |entry| { entry.toggle_cancelled(); }))
+        .on_pre_event('u', undo_change)
+        .on_pre_event(Event::CtrlChar('r'), redo_change)
+        .on_pre_event('o', |s| {
+            let selected = {
+                let results: ViewRef<OnEventView<QueryResults>> = s.find_id("query-results").expect("Unable to get query-results view");
+                results.get_inner().selection().map(|found| (found.0.clone(), found.1.id))
+            };
+            if let Some((page, id)) = selected {
+                open_entry(s, &page, id);
+            }
+        })
+        .with_id("query-results");
+
+    let query_view = OnEventView::new(LinearLayout::vertical()
+                                      .child(title)
+                                      .child(TextView::new(match count {
+                                          0 => "Nothing matches".to_string(),
+                                          1 => "1 entry".to_string(),
+                                          n => format!("{} entries", n)
+                                      }).align(Align::center()))
+                                      .child(list))
+        .on_pre_event(':', query_dialog)
+        .on_pre_event(Key::Esc, |s| {
+            s.pop_layer();
+            s.add_layer(daily_view(tz::home().today()));
+        })
+        .on_pre_event(Event::Char('?'), |s| {
+            let help_view = ListView::new()
+                .child("j", TextView::new("Move the selection cursor down"))
+                .child("k", TextView::new("Move the selection cursor up"))
+                .child("space", TextView::new("Toggle completion status"))
+                .child("X", TextView::new("Cancel the task, or reopen it if it's cancelled"))
+                .child("u", TextView::new("Undo the last change"))
+                .child("ctrl-r", TextView::new("Redo the last change you undid"))
+                .child("enter", TextView::new("Edit the entry"))
+                .child("o", TextView::new("Open the page the entry is on"))
+                .child(":", TextView::new("Run another query"))
+                .child("esc", TextView::new("Return to today's journal"))
+                .child("q", TextView::new("Quit"));
+            s.screen_mut().add_layer(Dialog::around(help_view).dismiss_button("Ok"));
+        });
+    Box::new(LinearLayout::horizontal()
+             .child(query_view.min_width(40))
+             .child(Dialog::around(TextView::new("Press ? for help")).title("Bullet Terminal")))
+}
+
+/// Change the selected result on its page, and show the change in the results
+fn update_result<F: FnOnce(&mut Entry)>(siv: &mut Cursive, change: F) {
+    let (idx, page, id) = {
+        let results: ViewRef<OnEventView<QueryResults>> = siv.find_id("query-results").expect("Unable to get query-results view");
+        let results = results.get_inner();
+        match (results.selected_id(), results.selection()) {
+            (Some(idx), Some(found)) => (idx, found.0.clone(), found.1.id),
+            _ => return
+        }
+    };
+    match history::update_entry(&page, id, change) {
+        Ok(Some(entry)) => replace_result(siv, idx, page, entry),
+        Ok(None) => siv.add_layer(Dialog::info("The entry has been deleted from its page")),
+        Err(e) => report(siv, Err(e))
+    }
+}
+
+fn replace_result(siv: &mut Cursive, idx: usize, page: Page, entry: Entry) {
+    let mut results: ViewRef<OnEventView<QueryResults>> = siv.find_id("query-results").expect("Unable to get query-results view");
+    let results = results.get_inner_mut();
+    if let Some((text, found)) = results.get_item_mut(idx) {
+        text.replace_range(0.., &result_label(&page, &entry));
+        *found = (page, entry);
+    }
+}
+
+/// Edit the contents of the selected result on its page
+fn edit_result(siv: &mut Cursive, entry: &Entry) {
+    siv.screen_mut().add_layer(
+        Dialog::around(EditView::new()
+                       .content(entry.content.clone())
+                       .on_submit(|s, text| {
+                           let text = text.to_string();
+                           s.pop_layer();
+                           update_result(s, |entry| entry.set_content(&text));
+                       })
+                       .min_width(30))
+            .title("Edit")
+            .dismiss_button("Cancel"));
+}
@@ -0,0 +1,63 @@
+use page::Page;
+use storage;
+use views::entries::{open_entry, report, select_nav};
+
+use cursive::Cursive;
+use cursive::traits::*;
+use cursive::views::{
+    ViewRef,
+    Dialog,
+    EditView,
+    LinearLayout,
+    OnEventView,
+    SelectView,
+    TextView};
+use uuid::Uuid;
+
+type ResultView = SelectView<(Page, Uuid)>;
+
+/// Pop up a search across every page of the journal. Picking a result replaces the on-screen page
+/// with the one holding it and selects the entry.
+pub fn search_dialog(siv: &mut Cursive) {
+    let results = ResultView::new().on_submit(|s, &(ref page, id): &(Page, Uuid)| {
+        // The search and the page it was opened from
+        s.pop_layer();
+        open_entry(s, page, id);
+    });
+    siv.screen_mut().add_layer(
+        Dialog::around(LinearLayout::vertical()
+                       .child(EditView::new()
+                              .on_submit(run_search)
+                              .min_width(40))
+                       .child(TextView::new("").with_id("search-status"))
+                       .child(select_nav(results).with_id("search-results").scrollable().max_height(15)))
+            .title("Search")
+            .dismiss_button("Close"));
+}
+
+/// Fill the results with the best matches for the query, and focus them
+fn run_search(siv: &mut Cursive, query: &str) {
+    let found = match storage::current().search(query) {
+        Ok(found) => found,
+        Err(e) => return report(siv, Err(e))
+    };
+    let count = found.len();
+    {
+        let mut results: ViewRef<OnEventView<ResultView>> = siv.find_id("search-results").expect("Unable to get search-results view");
+        let results = results.get_inner_mut();
+        results.clear();
+        for (page, entry) in found {
+            results.add_item(format!("{:<12} {}", page.to_string(), entry.to_display()), (page, entry.id));
+        }
+    }
+    if let Some(mut status) = siv.find_id::<TextView>("search-status") {
+        status.set_content(match count {
+            0 => "Nothing found".to_string(),
+            1 => "1 entry".to_string(),
+            n => format!("{} entries", n)
+        });
+    }
+    if count > 0 {
+        let _ = siv.focus_id("search-results");
+    }
+}
@@ -0,0 +1,69 @@
+use entry::{Entry, Signifier};
+use page::{self, Page};
+use storage::{self, StorageError};
+use views::entries::{report, select_nav};
+use views::page_view;
+
+use chrono::NaiveDate;
+use cursive::Cursive;
+use cursive::traits::*;
+use cursive::views::{Dialog, SelectView};
+
+/// Ask which signifier to filter by, then list every entry of the month containing `date` that
+/// has it
+pub fn signifier_filter_dialog(siv: &mut Cursive, date: NaiveDate) {
+    let mut signifiers = SelectView::new().on_submit(move |s, signifier: &Signifier| {
+        s.pop_layer();
+        match month_entries(date) {
+            Ok(entries) => filtered_dialog(s, date, *signifier, entries),
+            Err(e) => report(s, Err(e))
+        }
+    });
+    for signifier in Signifier::ALL.iter() {
+        signifiers.add_item(format!("{} {}", signifier.symbol(), signifier.name()), *signifier);
+    }
+    siv.screen_mut().add_layer(Dialog::around(select_nav(signifiers))
+                               .title("Show entries marked")
+                               .dismiss_button("Cancel"));
+}
+
+/// Every entry of the month's daily pages and of its monthly log, along with its page
+fn month_entries(date: NaiveDate) -> Result<Vec<(Page, Entry)>, StorageError> {
+    let storage = storage::current();
+    let mut entries: Vec<(Page, Entry)> = storage
+        .entries_between(page::first_of_month(date), page::add_months(date, 1))?
+        .into_iter()
+        .map(|(day, entry)| (Page::Day(day), entry))
+        .collect();
+    let month = Page::month_of(date);
+    for entry in storage.load(&month)? {
+        entries.push((month.clone(), entry));
+    }
+    Ok(entries)
+}
+
+/// List the entries with the signifier. Picking one replaces the on-screen page with the page
+/// it's on.
+fn filtered_dialog(siv: &mut Cursive, date: NaiveDate, signifier: Signifier, entries: Vec<(Page, Entry)>) {
+    let title = format!("Entries marked {} in {}", signifier.symbol(), date.format("%B %Y"));
+    let mut found = SelectView::new().on_submit(move |s, page: &Page| {
+        // Both the filter results and the page they were opened from
+        s.pop_layer();
+        s.pop_layer();
+        s.add_layer(page_view(page, date));
+    });
+    for (page, entry) in entries.into_iter().filter(|(_, entry)| entry.signifiers.contains(&signifier)) {
+        let when = match page {
+            Page::Day(day) => day.format("%d %a").to_string(),
+            _ => "month ".to_string()
+        };
+        found.add_item(format!("{} {}", when, entry.to_display()), page);
+    }
+    if found.is_empty() {
+        siv.add_layer(Dialog::info(format!("Nothing is marked {} in {}", signifier.symbol(), date.format("%B %Y"))));
+        return;
+    }
+    siv.screen_mut().add_layer(Dialog::around(select_nav(found).scrollable().max_height(20))
+                               .title(title)
+                               .dismiss_button("Close"));
+}
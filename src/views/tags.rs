@@ -0,0 +1,148 @@
+use entry::{words_marked, Entry};
+use page::Page;
+use storage;
+use tz;
+use views::daily::daily_view;
+use views::entries::{open_entry, select_nav};
+use views::readable_entries;
+
+use std::boxed::Box;
+use std::collections::BTreeMap;
+
+use cursive::Cursive;
+use cursive::view::View;
+use cursive::align::Align;
+use cursive::traits::*;
+use cursive::event::{Event, Key};
+use cursive::theme::{BaseColor, Color, Effect, Style};
+use cursive::utils::span::SpannedString;
+use cursive::views::{
+    Dialog,
+    TextView,
+    LinearLayout,
+    ListView,
+    SelectView,
+    OnEventView};
+use uuid::Uuid;
+
+/// A `#tag` or an `@context`, written with its marker
+type Label = String;
+
+/// The content with its `#tags` and `@contexts` picked out in their own colours
+pub fn highlighted(content: &str) -> SpannedString<Style> {
+    let mut text = SpannedString::new();
+    for (idx, word) in content.split(' ').enumerate() {
+        if idx > 0 {
+            text.append_plain(" ");
+        }
+        if !words_marked(word, '#').is_empty() {
+            text.append_styled(word, Color::Light(BaseColor::Cyan));
+        } else if !words_marked(word, '@').is_empty() {
+            text.append_styled(word, Color::Light(BaseColor::Magenta));
+        } else {
+            text.append_plain(word);
+        }
+    }
+    text
+}
+
+/// Every tag and context used in the entries, with how many entries use it
+fn label_counts(entries: &[(Page, Entry)]) -> BTreeMap<Label, usize> {
+    let mut counts = BTreeMap::new();
+    for (_, entry) in entries {
+        for label in labels(entry) {
+            *counts.entry(label).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Every entry in the journal. Pages that can't be read are reported and left out.
+fn journal_entries() -> Vec<(Page, Entry)> {
+    match storage::current().entries() {
+        Ok(entries) => entries,
+        // Go through the pages one by one, so a page that can't be read doesn't hide the rest
+        Err(_) => readable_entries(|_| true)
+    }
+}
+
+/// The entry's tags and contexts, written with their markers
+fn labels(entry: &Entry) -> Vec<Label> {
+    entry.tags.iter().map(|tag| format!("#{}", tag))
+        .chain(entry.contexts.iter().map(|context| format!("@{}", context)))
+        .collect()
+}
+
+/// The tag browser: every #tag and @context in the journal with the number of entries using it
+pub fn tag_index_view() -> Box<dyn View> {
+    let title = TextView::new(SpannedString::styled("Tags and contexts", Effect::Bold))
+        .align(Align::center());
+    let mut select = SelectView::new().on_submit(|s, label: &Label| {
+        s.pop_layer();
+        s.add_layer(tag_view(label));
+    });
+    for (label, count) in label_counts(&journal_entries()) {
+        select.add_item(format!("{} ({})", label, count), label);
+    }
+
+    let index_view = OnEventView::new(LinearLayout::vertical()
+                                      .child(title)
+                                      .child(select_nav(select)))
+        .on_pre_event(Key::Esc, |s| {
+            s.pop_layer();
+            s.add_layer(daily_view(tz::home().today()));
+        })
+        .on_pre_event(Event::Char('?'), |s| {
+            let help_view = ListView::new()
+                .child("j", TextView::new("Move the selection cursor down"))
+                .child("k", TextView::new("Move the selection cursor up"))
+                .child("enter", TextView::new("List every entry with the tag or context"))
+                .child("esc", TextView::new("Return to today's journal"))
+                .child("q", TextView::new("Quit"));
+            s.screen_mut().add_layer(Dialog::around(help_view).dismiss_button("Ok"));
+        });
+    Box::new(LinearLayout::horizontal()
+             .child(index_view.min_width(40))
+             .child(Dialog::around(TextView::new("Press ? for help")).title("Bullet Terminal")))
+}
+
+/// A virtual collection of every entry with the tag or context, gathered from all pages.
+/// Entries are edited on their own pages, which enter opens.
+fn tag_view(label: &str) -> Box<dyn View> {
+    let title = TextView::new(SpannedString::styled(label.to_string(), Effect::Bold))
+        .align(Align::center());
+    let mut select = SelectView::new().on_submit(|s, &(ref page, id): &(Page, Uuid)| open_entry(s, page, id));
+    for (page, entry) in journal_entries().into_iter().filter(|(_, entry)| labels(entry).iter().any(|l| l == label)) {
+        select.add_item(format!("{:<12} {}", page.to_string(), entry.to_display()), (page, entry.id));
+    }
+
+    let tag_view = OnEventView::new(LinearLayout::vertical()
+                                    .child(title)
+                                    .child(select_nav(select)))
+        .on_pre_event(Key::Esc, |s| {
+            s.pop_layer();
+            s.add_layer(tag_index_view());
+        })
+        .on_pre_event(Event::Char('?'), |s| {
+            let help_view = ListView::new()
+                .child("enter", TextView::new("Open the page the entry is on"))
+                .child("esc", TextView::new("Return to the list of tags"))
+                .child("q", TextView::new("Quit"));
+            s.screen_mut().add_layer(Dialog::around(help_view).dismiss_button("Ok"));
+        });
+    Box::new(LinearLayout::horizontal()
+             .child(tag_view.min_width(40))
+             .child(Dialog::around(TextView::new("Press ? for help")).title("Bullet Terminal")))
+}
+
+/// Show the selected entry with its tags and contexts highlighted under the entry list
+pub fn show_highlight(siv: &mut Cursive, entry: &Entry) {
+    if let Some(mut view) = siv.find_id::<TextView>("entry-highlight") {
+        if entry.tags.is_empty() && entry.contexts.is_empty() {
+            view.set_content("");
+        } else {
+            view.set_content(highlighted(&entry.content));
+        }
+    }
+}
+
@@ -0,0 +1,30 @@
+use storage;
+use views::daily::daily_view;
+
+use std::boxed::Box;
+
+use chrono::NaiveDate;
+use cursive::view::View;
+use cursive::traits::*;
+use cursive::views::{Dialog, LinearLayout, TextView};
+
+/// Shown on startup when the journal was written by an older version. Lists every change that
+/// will be made and only touches the journal once they're accepted.
+pub fn upgrade_view(changes: &[String], today: NaiveDate) -> Box<dyn View> {
+    let report = TextView::new(changes.join("\n")).scrollable().max_height(15);
+    Box::new(Dialog::around(LinearLayout::vertical()
+                            .child(TextView::new("Your journal was saved by an older version of bullet-terminal \
+                                                  and needs to be moved into the new layout:\n"))
+                            .child(report))
+             .title("Upgrade your journal")
+             .button("Upgrade", move |s| {
+                 if let Err(e) = storage::current().upgrade() {
+                     s.add_layer(Dialog::info(format!("Unable to upgrade the journal: {}", e)));
+                     return;
+                 }
+                 s.pop_layer();
+                 s.add_layer(daily_view(today));
+             })
+             .button("Quit", |s| s.quit())
+             .max_width(80))
+}